@@ -0,0 +1,192 @@
+//! Stable-Rust benchmarks of compression and decompression over the files in `benches/data`
+//! and a few synthetic inputs.
+//!
+//! Usage: cargo bench --bench corpus -- [FILTER] [--time SECS] [--save-baseline NAME] [--baseline NAME]
+//!
+//! Each measurement reports the median time per iteration over a number of samples, converted
+//! to MB/s. With `--save-baseline` the results are saved under `target/bench-baselines`, and
+//! with `--baseline` they are compared against a previously saved run.
+
+extern crate rsnappy;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use rsnappy::{compress, decompress};
+
+static TEXT: &'static str = include_str!("../tests/moonstone-short.txt");
+
+const SAMPLES: usize = 20;
+
+struct Args {
+    filter: Option<String>,
+    time: Duration,
+    save_baseline: Option<String>,
+    baseline: Option<String>,
+}
+
+struct BenchResult {
+    name: String,
+    ratio: f64,
+    compress_mbps: f64,
+    decompress_mbps: f64,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        filter: None,
+        time: Duration::from_secs(1),
+        save_baseline: None,
+        baseline: None,
+    };
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match &arg[..] {
+            "--bench"         => { },  // Passed by cargo bench
+            "--time"          => {
+                let secs: f64 = argv.next().and_then(|s| s.parse().ok()).expect("--time requires a number of seconds");
+                args.time = Duration::from_millis((secs * 1000.0) as u64);
+            },
+            "--save-baseline" => args.save_baseline = Some(argv.next().expect("--save-baseline requires a name")),
+            "--baseline"      => args.baseline = Some(argv.next().expect("--baseline requires a name")),
+            _ if arg.starts_with("-") => panic!("unknown option {}", arg),
+            _                 => args.filter = Some(arg)
+        }
+    }
+    args
+}
+
+/// The benchmark inputs, as (name, data) pairs.
+fn inputs() -> Vec<(String, Vec<u8>)> {
+    let mut inputs = Vec::new();
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches").join("data");
+    let mut paths: Vec<PathBuf> = fs::read_dir(data_dir).unwrap().map(|e| e.unwrap().path()).collect();
+    paths.sort();
+    for path in paths {
+        let mut data = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+        inputs.push((path.file_name().unwrap().to_string_lossy().into_owned(), data));
+    }
+
+    inputs.push(("synthetic/text".to_string(), TEXT.as_bytes().to_vec()));
+    inputs.push(("synthetic/short-text".to_string(), TEXT.as_bytes()[727..2000].to_vec()));
+    // xorshift, so the random input is the same in every run
+    let mut x: u32 = 2463534242;
+    let random = (0..TEXT.len()).map(|_| {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        x as u8
+    }).collect();
+    inputs.push(("synthetic/random".to_string(), random));
+    inputs.push(("synthetic/zeros".to_string(), vec![0; 1 << 20]));
+    inputs
+}
+
+/// Returns the median time of one call to `f`.
+fn measure<F: FnMut()>(time: Duration, mut f: F) -> Duration {
+    // Warm up, and estimate how many iterations fit in one sample.
+    let start = Instant::now();
+    let mut warmup_iterations = 0u32;
+    while warmup_iterations < 3 || start.elapsed() < time / 10 {
+        f();
+        warmup_iterations += 1;
+    }
+    let per_iteration = start.elapsed() / warmup_iterations;
+    let sample_time = time / SAMPLES as u32;
+    let iterations = ::std::cmp::max(1, (sample_time.as_secs_f64() / per_iteration.as_secs_f64()) as u32);
+
+    let mut samples: Vec<Duration> = (0..SAMPLES).map(|_| {
+        let start = Instant::now();
+        for _ in 0..iterations {
+            f();
+        }
+        start.elapsed() / iterations
+    }).collect();
+    samples.sort();
+    samples[SAMPLES / 2]
+}
+
+fn mbps(bytes: usize, time: Duration) -> f64 {
+    bytes as f64 / time.as_secs_f64() / 1_000_000.0
+}
+
+fn bench(args: &Args, name: String, input: &[u8]) -> BenchResult {
+    let mut compressed = Vec::with_capacity(input.len() * 2);
+    let compress_time = measure(args.time, || {
+        compressed.clear();
+        compress(&mut Cursor::new(input), &mut compressed).unwrap();
+    });
+    let mut decompressed = Vec::with_capacity(input.len());
+    let decompress_time = measure(args.time, || {
+        decompressed.clear();
+        decompress(&mut Cursor::new(&compressed[..]), &mut decompressed).unwrap();
+    });
+    assert!(&decompressed[..] == input, "roundtrip mismatch for {}", name);
+    BenchResult {
+        name: name,
+        ratio: input.len() as f64 / compressed.len() as f64,
+        compress_mbps: mbps(input.len(), compress_time),
+        decompress_mbps: mbps(input.len(), decompress_time),
+    }
+}
+
+fn baseline_path(name: &str) -> PathBuf {
+    let target_dir = env::var("CARGO_TARGET_DIR").map(PathBuf::from)
+                         .unwrap_or(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target"));
+    target_dir.join("bench-baselines").join(format!("{}.tsv", name))
+}
+
+fn save_baseline(name: &str, results: &[BenchResult]) {
+    let path = baseline_path(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut f = File::create(&path).unwrap();
+    for r in results {
+        writeln!(f, "{}\t{}\t{}\t{}", r.name, r.ratio, r.compress_mbps, r.decompress_mbps).unwrap();
+    }
+    println!("Saved baseline to {}", path.display());
+}
+
+/// Returns (compress MB/s, decompress MB/s) by benchmark name.
+fn load_baseline(name: &str) -> HashMap<String, (f64, f64)> {
+    let path = baseline_path(name);
+    let mut s = String::new();
+    File::open(&path).unwrap_or_else(|e| panic!("could not open {}: {}", path.display(), e))
+        .read_to_string(&mut s).unwrap();
+    s.lines().map(|line| {
+        let fields: Vec<&str> = line.split('\t').collect();
+        (fields[0].to_string(), (fields[2].parse().unwrap(), fields[3].parse().unwrap()))
+    }).collect()
+}
+
+fn change(new: f64, old: Option<f64>) -> String {
+    match old {
+        Some(old) => format!(" ({:+6.1}%)", 100.0 * (new - old) / old),
+        None      => String::new()
+    }
+}
+
+fn main() {
+    let args = parse_args();
+    let baseline = args.baseline.as_ref().map(|b| load_baseline(b));
+    let mut results = Vec::new();
+    for (name, input) in inputs() {
+        if args.filter.as_ref().map_or(false, |f| !name.contains(&f[..])) {
+            continue;
+        }
+        let r = bench(&args, name, &input[..]);
+        let old = baseline.as_ref().and_then(|b| b.get(&r.name));
+        println!("{:<22} {:>8} bytes  ratio {:>6.3}  compress {:>8.1} MB/s{}  decompress {:>8.1} MB/s{}",
+                 r.name, input.len(), r.ratio,
+                 r.compress_mbps, change(r.compress_mbps, old.map(|o| o.0)),
+                 r.decompress_mbps, change(r.decompress_mbps, old.map(|o| o.1)));
+        results.push(r);
+    }
+    if let Some(ref name) = args.save_baseline {
+        save_baseline(name, &results[..]);
+    }
+}
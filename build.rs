@@ -1,14 +1,13 @@
 use std::env;
-use std::fs::{File, read_dir};
+use std::fs::File;
 use std::io::Write;
 use std::path::Path;
-use std::iter::IntoIterator;
 
 fn main() {
     let s = env::var("OUT_DIR").unwrap();
     let out_dir = Path::new(&s);
     write_tables_rs(&out_dir);
-    write_benchmarks(&out_dir);
+    write_crc_tables_rs(&out_dir);
 }
 
 fn write_tables_rs(out_dir: &Path) {
@@ -38,47 +37,22 @@ fn write_tables_rs(out_dir: &Path) {
     }
     f.write_all(b"];\n").unwrap();
 
-    f.write_all(b"#[inline]\nfn get_tag_size(c: u8) -> usize { (TAG_SIZE[c as usize] + 1) as usize }\n").unwrap();
+    f.write_all(b"#[inline]\npub fn get_tag_size(c: u8) -> usize { (TAG_SIZE[c as usize] + 1) as usize }\n").unwrap();
 }
 
-fn write_benchmarks(out_dir: &Path) {
-    let s = env::var("CARGO_MANIFEST_DIR").unwrap();
-    let bench_data_dir = Path::new(&s).join("benches").join("data");
-    let bench_files = read_dir(bench_data_dir).unwrap().map(|e| {
-        let e = e.unwrap();
-        let mut s = String::new();
-        for &c in e.path().file_name().unwrap().to_string_lossy().as_bytes() {
-            if (c >= b'0' && c <= b'9') || (c >= b'a' && c <= b'z') || (c >= b'A' && c <= b'Z') || c == b'_' {
-                s.push(c as char);
-            } else {
-                s.push('_');
-            }
-        }
-        (e.path().to_string_lossy().to_string(), s)
-    });
-    do_write_benchmarks(out_dir, bench_files);
-}
+fn write_crc_tables_rs(out_dir: &Path) {
+    let dest_path = out_dir.join("crc_tables.rs");
+    let mut f = File::create(&dest_path).unwrap();
 
-fn do_write_benchmarks<I: IntoIterator<Item=(String, String)>>(out_dir: &Path, files: I) {
-    let mut f = File::create(out_dir.join("generated-benches.rs")).unwrap();
-    writeln!(f, "extern crate test;").unwrap();
-    writeln!(f, "extern crate rsnappy;").unwrap();
-    writeln!(f, "use std::io::{{Read, Cursor}};").unwrap();
-    writeln!(f, "use std::path::Path;").unwrap();
-    writeln!(f, "use std::fs::File;").unwrap();
-    for (path, name) in files {
-        writeln!(f,r##"
-#[bench]
-fn bench_{}(bench: &mut test::Bencher) {{
-    let mut f = File::open(Path::new("{}")).unwrap();
-    let mut input = Vec::new();
-    f.read_to_end(&mut input);
-    let mut output = Vec::with_capacity(input.len() * 2);
-    bench.iter(|| {{
-        rsnappy::compress(&mut Cursor::new(&input[..]), &mut output).unwrap();
-        output.clear();
-    }});
-    bench.bytes = input.len() as u64;
-}}"##, name, path).unwrap();
+    // Table for the reflected CRC-32C (Castagnoli) polynomial
+    const POLY: u32 = 0x82F63B78;
+    f.write_all(b"const CRC32C_TABLE: [u32; 256] = [\n").unwrap();
+    for i in 0..256u32 {
+        let mut crc = i;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+        }
+        write!(&mut f, "\t0x{:08X},\n", crc).unwrap();
     }
+    f.write_all(b"];\n").unwrap();
 }
@@ -0,0 +1,24 @@
+//! Raw decompression of arbitrary bytes must return an error rather than panic or misbehave.
+
+#![no_main]
+
+use std::io::Cursor;
+use libfuzzer_sys::fuzz_target;
+
+fn uncompressed_len(data: &[u8]) -> Option<u32> {
+    let mut result: u32 = 0;
+    for (i, &c) in data.iter().enumerate().take(5) {
+        result |= ((c & 0x7F) as u32) << (7 * i);
+        if c & 0x80 == 0 {
+            return Some(result);
+        }
+    }
+    None
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut out = Vec::new();
+    if rsnappy::decompress(&mut Cursor::new(data), &mut out).is_ok() {
+        assert_eq!(Some(out.len() as u32), uncompressed_len(data));
+    }
+});
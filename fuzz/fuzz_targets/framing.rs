@@ -0,0 +1,21 @@
+//! Decoding arbitrary bytes as the framing format must return an error rather than panic,
+//! and anything that decodes must roundtrip through `FramedWriter`.
+
+#![no_main]
+
+use std::io::{Read, Write};
+use libfuzzer_sys::fuzz_target;
+use rsnappy::{FramedReader, FramedWriter};
+
+fuzz_target!(|data: &[u8]| {
+    let mut out = Vec::new();
+    if FramedReader::new(data).read_to_end(&mut out).is_err() {
+        return;
+    }
+    let mut writer = FramedWriter::new(Vec::new());
+    writer.write_all(&out).unwrap();
+    let compressed = writer.into_inner().unwrap();
+    let mut roundtripped = Vec::new();
+    FramedReader::new(&compressed[..]).read_to_end(&mut roundtripped).unwrap();
+    assert!(roundtripped == out);
+});
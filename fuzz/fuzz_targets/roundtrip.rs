@@ -0,0 +1,29 @@
+//! Compressing arbitrary data with arbitrary options and decompressing it must give back the input,
+//! and the compressed data must fit in `max_compressed_length`.
+
+#![no_main]
+
+use std::io::Cursor;
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use rsnappy::CompressorOptions;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    block_size: u16,
+    window_size: u32,
+    data: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let options = CompressorOptions {
+        block_size: input.block_size,
+        window_size: input.window_size % (rsnappy::MAX_WINDOW_SIZE + 1),
+    };
+    let mut compressed = Vec::new();
+    rsnappy::compress_with_options(&mut Cursor::new(&input.data[..]), &mut compressed, &options).unwrap();
+    assert!(compressed.len() <= rsnappy::max_compressed_length(input.data.len()));
+    let mut out = Vec::new();
+    rsnappy::decompress(&mut Cursor::new(&compressed[..]), &mut out).unwrap();
+    assert!(out == input.data);
+});
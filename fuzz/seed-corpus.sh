@@ -0,0 +1,21 @@
+#!/bin/sh
+# Fills corpus/<target> with seeds made from the files in benches/data.
+# Run from anywhere; then e.g. `cargo fuzz run decompress` from the fuzz directory.
+set -e
+
+fuzz_dir=$(cd "$(dirname "$0")" && pwd)
+root=$(dirname "$fuzz_dir")
+data="$root/benches/data"
+
+cargo build --quiet --release --manifest-path "$root/Cargo.toml" --bin rsnap
+rsnap="$root/target/release/rsnap"
+
+mkdir -p "$fuzz_dir/corpus/decompress" "$fuzz_dir/corpus/roundtrip" "$fuzz_dir/corpus/framing"
+for f in "$data"/*; do
+    name=$(basename "$f")
+    "$rsnap" -c -F raw < "$f" > "$fuzz_dir/corpus/decompress/$name"
+    "$rsnap" -c -F framed < "$f" > "$fuzz_dir/corpus/framing/$name"
+    # The roundtrip target reads its block size from the first two bytes and its window size
+    # from the next four.
+    { printf '\377\377\0\0\1\0'; cat "$f"; } > "$fuzz_dir/corpus/roundtrip/$name"
+done
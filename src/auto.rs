@@ -0,0 +1,254 @@
+//! Decompression of streams in any of the supported container formats.
+
+use std::io::{Read, Cursor, Chain};
+use std::io;
+use std::fmt;
+use decompress::{decompress, SnappyError};
+use framing::{FramedReader, FRAMING_MAGIC};
+use hadoop::HadoopReader;
+use xerial::{read_rest_of_header, XerialReader, XERIAL_MAGIC};
+use util::{read_exact_or_eof, read_u32_be, read_u32_be_or_eof, read_chunk};
+
+/// The container formats that can be detected by `detect_format`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A single raw Snappy block, starting with the varint uncompressed length.
+    Raw,
+    /// The Snappy framing format, starting with a stream identifier chunk.
+    Framed,
+    /// Hadoop's `SnappyCodec` block stream.
+    Hadoop,
+    /// snappy-java's `SnappyOutputStream`.
+    Xerial,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Format::Raw    => "raw",
+            Format::Framed => "framed",
+            Format::Hadoop => "hadoop",
+            Format::Xerial => "xerial",
+        })
+    }
+}
+
+/// How many bytes from the start of a stream `detect_format` needs to see to give its best guess.
+pub const DETECT_PREFIX_LEN: usize = 16;
+
+/// Guesses the format of a stream from its first bytes.
+///
+/// The framing and snappy-java formats have magic bytes and are detected reliably.
+/// Hadoop streams are recognized by a block header followed by a chunk whose
+/// uncompressed length fits in the block. Anything else is assumed to be raw.
+pub fn detect_format(prefix: &[u8]) -> Format {
+    if prefix.starts_with(&FRAMING_MAGIC) {
+        Format::Framed
+    } else if prefix.starts_with(&XERIAL_MAGIC) {
+        Format::Xerial
+    } else if looks_like_hadoop(prefix) {
+        Format::Hadoop
+    } else {
+        Format::Raw
+    }
+}
+
+fn looks_like_hadoop(prefix: &[u8]) -> bool {
+    if prefix.len() < 9 {
+        return false;
+    }
+    let read_u32_be = |b: &[u8]| ((b[0] as u64) << 24) | ((b[1] as u64) << 16) | ((b[2] as u64) << 8) | b[3] as u64;
+    let block_len = read_u32_be(&prefix[0..4]);
+    let chunk_len = read_u32_be(&prefix[4..8]);
+    let chunk_uncompressed_len = match read_varint(&prefix[8..]) {
+        Some(n) => n as u64,
+        None    => return false
+    };
+    // The compressed length must be possible for the uncompressed length
+    block_len > 0 && chunk_uncompressed_len > 0 && chunk_uncompressed_len <= block_len &&
+        chunk_len > 1 && chunk_len <= 32 + chunk_uncompressed_len + chunk_uncompressed_len / 6
+}
+
+fn read_varint(buf: &[u8]) -> Option<u32> {
+    let mut result: u32 = 0;
+    for (i, &c) in buf.iter().enumerate().take(5) {
+        result |= ((c & 0x7F) as u32) << (7 * i);
+        if c & 0x80 == 0 {
+            return Some(result);
+        }
+    }
+    None
+}
+
+type Prefixed<R> = Chain<Cursor<Vec<u8>>, R>;
+
+enum Decoder<R: Read> {
+    Raw(Cursor<Vec<u8>>),
+    Framed(FramedReader<Prefixed<R>>),
+    Hadoop(HadoopReader<Prefixed<R>>),
+    Xerial(XerialReader<Prefixed<R>>),
+}
+
+/// Decompresses a stream in whichever format `detect_format` finds.
+///
+/// Raw streams are decompressed as a whole when the decoder is created,
+/// since the raw format can only be decoded in one go.
+pub struct AutoDecoder<R: Read> {
+    format: Format,
+    decoder: Decoder<R>,
+}
+
+impl <R: Read> AutoDecoder<R> {
+    pub fn new(mut reader: R) -> io::Result<AutoDecoder<R>> {
+        let mut prefix = Vec::with_capacity(DETECT_PREFIX_LEN);
+        try!((&mut reader).take(DETECT_PREFIX_LEN as u64).read_to_end(&mut prefix));
+        let format = detect_format(&prefix[..]);
+        let inp = Cursor::new(prefix).chain(reader);
+        let decoder = match format {
+            Format::Raw    => {
+                let mut inp = inp;
+                let mut compressed = Vec::new();
+                try!(inp.read_to_end(&mut compressed));
+                let mut out = Vec::new();
+                try!(decompress(&mut Cursor::new(&compressed[..]), &mut out));
+                Decoder::Raw(Cursor::new(out))
+            },
+            Format::Framed => Decoder::Framed(FramedReader::new(inp)),
+            Format::Hadoop => Decoder::Hadoop(HadoopReader::new(inp)),
+            Format::Xerial => Decoder::Xerial(XerialReader::new(inp)),
+        };
+        Ok(AutoDecoder {
+            format: format,
+            decoder: decoder,
+        })
+    }
+
+    /// The detected format of the stream.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+}
+
+impl <R: Read> Read for AutoDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.decoder {
+            Decoder::Raw(ref mut r)    => r.read(buf),
+            Decoder::Framed(ref mut r) => r.read(buf),
+            Decoder::Hadoop(ref mut r) => r.read(buf),
+            Decoder::Xerial(ref mut r) => r.read(buf),
+        }
+    }
+}
+
+/// Decompresses `input` in whichever format `detect_format` finds, and returns the detected format.
+pub fn decompress_auto(input: &[u8]) -> Result<(Vec<u8>, Format), SnappyError> {
+    let format = detect_format(input);
+    let mut out = Vec::new();
+    let result = match format {
+        Format::Raw    => return decompress(&mut Cursor::new(input), &mut out).map(|_| (out, format)),
+        Format::Framed => FramedReader::new(input).read_to_end(&mut out),
+        Format::Hadoop => HadoopReader::new(input).read_to_end(&mut out),
+        Format::Xerial => XerialReader::new(input).read_to_end(&mut out),
+    };
+    match result {
+        Ok(_)  => Ok((out, format)),
+        Err(e) => Err(SnappyError::from(e))
+    }
+}
+
+/// Sizes and chunk count of a compressed stream, as reported by `stream_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub format: Format,
+    pub compressed_len: u64,
+    /// The sum of the uncompressed lengths stored in the stream's headers.
+    pub uncompressed_len: u64,
+    /// The number of independently compressed pieces of data in the stream.
+    pub chunks: u64,
+}
+
+fn format_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn raw_length(chunk: &[u8]) -> io::Result<u64> {
+    match read_varint(chunk) {
+        Some(n) => Ok(n as u64),
+        None    => Err(format_error("invalid uncompressed length"))
+    }
+}
+
+/// Walks the headers of a stream in whichever format `detect_format` finds, without decompressing it.
+///
+/// Only the structure of the stream is checked, not the compressed data or checksums.
+/// Use an `AutoDecoder` to verify those.
+pub fn stream_info<R: Read>(mut reader: R) -> io::Result<StreamInfo> {
+    let mut prefix = Vec::with_capacity(DETECT_PREFIX_LEN);
+    try!((&mut reader).take(DETECT_PREFIX_LEN as u64).read_to_end(&mut prefix));
+    let format = detect_format(&prefix[..]);
+    let mut inp = Cursor::new(prefix).chain(reader);
+    let mut info = StreamInfo {
+        format: format,
+        compressed_len: 0,
+        uncompressed_len: 0,
+        chunks: 0,
+    };
+    let mut chunk = Vec::new();
+    match format {
+        Format::Raw => {
+            try!(inp.read_to_end(&mut chunk));
+            info.compressed_len = chunk.len() as u64;
+            info.uncompressed_len = try!(raw_length(&chunk[..]));
+            info.chunks = 1;
+        },
+        Format::Framed => {
+            let mut header = [0; 4];
+            while try!(read_exact_or_eof(&mut inp, &mut header)) {
+                let len = (header[1] as u32) | ((header[2] as u32) << 8) | ((header[3] as u32) << 16);
+                try!(read_chunk(&mut inp, len, &mut chunk));
+                info.compressed_len += 4 + len as u64;
+                match header[0] {
+                    0x00 | 0x01 if chunk.len() < 4 => return Err(format_error("data chunk too short for checksum")),
+                    0x00 => info.uncompressed_len += try!(raw_length(&chunk[4..])),
+                    0x01 => info.uncompressed_len += len as u64 - 4,
+                    0x02..=0x7F => return Err(format_error("reserved unskippable chunk")),
+                    _ => continue
+                }
+                info.chunks += 1;
+            }
+        },
+        Format::Hadoop => {
+            while let Some(block_len) = try!(read_u32_be_or_eof(&mut inp)) {
+                info.compressed_len += 4;
+                info.uncompressed_len += block_len as u64;
+                let mut block_remaining = block_len as u64;
+                while block_remaining > 0 {
+                    let len = try!(read_u32_be(&mut inp));
+                    try!(read_chunk(&mut inp, len, &mut chunk));
+                    let chunk_len = try!(raw_length(&chunk[..]));
+                    if chunk_len > block_remaining {
+                        return Err(format_error("chunks decompress to more than the block length"));
+                    }
+                    block_remaining -= chunk_len;
+                    info.compressed_len += 4 + len as u64;
+                    info.chunks += 1;
+                }
+            }
+        },
+        Format::Xerial => {
+            while let Some(len) = try!(read_u32_be_or_eof(&mut inp)) {
+                if (len >> 24) as u8 == XERIAL_MAGIC[0] {
+                    // The header of this or a concatenated stream
+                    try!(read_rest_of_header(&mut inp, len));
+                    info.compressed_len += 16;
+                    continue;
+                }
+                try!(read_chunk(&mut inp, len, &mut chunk));
+                info.compressed_len += 4 + len as u64;
+                info.uncompressed_len += try!(raw_length(&chunk[..]));
+                info.chunks += 1;
+            }
+        }
+    }
+    Ok(info)
+}
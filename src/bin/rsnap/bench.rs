@@ -0,0 +1,152 @@
+//! `rsnap bench`: in-memory compression and decompression throughput, similar to `lz4 -b`.
+
+use std::fs::File;
+use std::io;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use rsnappy::{compress_with_options, decompress, CompressorOptions, MAX_BLOCK_SIZE};
+
+static USAGE: &'static str = "
+Usage: rsnap bench [options] <file>...
+
+Compresses and decompresses each file in memory, repeatedly for each block size,
+and prints the compression ratio and throughput.
+
+Options:
+  -h, --help               Show this message.
+  -b, --block-sizes=<kb>   Comma separated block sizes to test, in kb (default 4,16,32,64).
+                           64 means the largest block size, 65535 bytes.
+  -T, --time=<seconds>     Minimum time to spend measuring each direction for
+                           each file and block size (default 1).
+";
+
+struct BenchArgs {
+    files: Vec<String>,
+    block_sizes: Vec<u16>,
+    time: Duration,
+}
+
+fn parse_block_sizes(s: &str) -> Result<Vec<u16>, String> {
+    let mut sizes = Vec::new();
+    for part in s.split(',') {
+        let kb = try!(part.trim().parse::<usize>().map_err(|_| format!("invalid block size '{}'", part)));
+        match kb.checked_mul(1024) {
+            Some(b) if b > 0 && b <= MAX_BLOCK_SIZE + 1 => sizes.push(::std::cmp::min(b, MAX_BLOCK_SIZE) as u16),
+            _ => return Err(format!("block size must be between 1kb and {}kb", (MAX_BLOCK_SIZE + 1) / 1024))
+        }
+    }
+    Ok(sizes)
+}
+
+fn parse_args(argv: &[String]) -> Result<BenchArgs, String> {
+    let mut args = BenchArgs {
+        files: Vec::new(),
+        block_sizes: vec![4 * 1024, 16 * 1024, 32 * 1024, MAX_BLOCK_SIZE as u16],
+        time: Duration::from_secs(1),
+    };
+    let mut i = 0;
+    while i < argv.len() {
+        let arg = &argv[i];
+        i += 1;
+        if !arg.starts_with("-") {
+            args.files.push(arg.clone());
+            continue;
+        }
+        let (name, inline) = match arg.find('=') {
+            Some(eq) => (&arg[..eq], Some(arg[eq + 1..].to_string())),
+            None     => (&arg[..], None)
+        };
+        let value = match inline {
+            Some(v) => v,
+            None if i < argv.len() => { i += 1; argv[i - 1].clone() },
+            None => return Err(format!("option '{}' requires a value", name))
+        };
+        match name {
+            "-b" | "--block-sizes" => args.block_sizes = try!(parse_block_sizes(&value)),
+            "-T" | "--time"        => {
+                let secs = try!(value.parse::<f64>().map_err(|_| format!("invalid time '{}'", value)));
+                if !(secs >= 0.0) {
+                    return Err(format!("invalid time '{}'", value));
+                }
+                args.time = Duration::from_millis((secs * 1000.0) as u64);
+            },
+            _ => return Err(format!("unknown option '{}'", name))
+        }
+    }
+    if args.files.is_empty() {
+        return Err("no files to benchmark".to_string());
+    }
+    Ok(args)
+}
+
+/// Runs `f` at least once and until `time` has passed, returning the throughput in MB/s.
+fn measure<F: FnMut() -> io::Result<()>>(bytes: usize, time: Duration, mut f: F) -> io::Result<f64> {
+    let start = Instant::now();
+    let mut iterations = 0u64;
+    loop {
+        try!(f());
+        iterations += 1;
+        if start.elapsed() >= time {
+            break;
+        }
+    }
+    let secs = start.elapsed().as_secs_f64();
+    Ok((bytes as f64 * iterations as f64) / secs / 1_000_000.0)
+}
+
+fn bench_file(args: &BenchArgs, name: &str) -> io::Result<()> {
+    let mut input = Vec::new();
+    try!(try!(File::open(name)).read_to_end(&mut input));
+    if input.len() > ::std::u32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "file too large for the raw format"));
+    }
+    let short_name = Path::new(name).file_name().map(|n| n.to_string_lossy().into_owned())
+                                                .unwrap_or(name.to_string());
+    for &block_size in args.block_sizes.iter() {
+        let options = CompressorOptions { block_size: block_size, ..Default::default() };
+        let mut compressed = Vec::with_capacity(input.len());
+        let compress_speed = try!(measure(input.len(), args.time, || {
+            compressed.clear();
+            compress_with_options(&mut Cursor::new(&input[..]), &mut compressed, &options)
+        }));
+        let mut decompressed = Vec::with_capacity(input.len());
+        let decompress_speed = try!(measure(input.len(), args.time, || {
+            decompressed.clear();
+            decompress(&mut Cursor::new(&compressed[..]), &mut decompressed).map_err(io::Error::from)
+        }));
+        if decompressed != input {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "roundtrip mismatch"));
+        }
+        let ratio = if compressed.is_empty() { 0.0 } else { input.len() as f64 / compressed.len() as f64 };
+        println!("{:<20} {:>5}K {:>10} -> {:>10} ({:>6.3}) {:>9.1} MB/s {:>9.1} MB/s",
+                 short_name, (block_size as usize + 1) / 1024, input.len(), compressed.len(), ratio,
+                 compress_speed, decompress_speed);
+    }
+    Ok(())
+}
+
+/// Runs the subcommand, returning the exit code.
+pub fn main(argv: &[String]) -> i32 {
+    if argv.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE.trim());
+        return 0;
+    }
+    let args = match parse_args(argv) {
+        Ok(args) => args,
+        Err(msg) => {
+            let _ = writeln!(io::stderr(), "rsnap bench: {}\nTry 'rsnap bench --help' for more information.", msg);
+            return 2;
+        }
+    };
+    println!("{:<20} {:>6} {:>10}    {:>10} {:>8} {:>14} {:>14}",
+             "file", "block", "size", "compressed", "ratio", "compress", "decompress");
+    let mut failed = false;
+    for name in args.files.iter() {
+        if let Err(e) = bench_file(&args, name) {
+            let _ = writeln!(io::stderr(), "rsnap bench: {}: {}", name, e);
+            failed = true;
+        }
+    }
+    if failed { 1 } else { 0 }
+}
@@ -0,0 +1,154 @@
+//! `rsnap dict`: trains a preset dictionary on sample messages and reports how much it helps.
+
+use std::fs::File;
+use std::io;
+use std::io::{Cursor, Read, Write};
+use rsnappy::{compress, compress_with_dictionary, train_dictionary, MAX_DICTIONARY_SIZE};
+
+static USAGE: &'static str = "
+Usage: rsnap dict [options] <sample>...
+
+Trains a preset dictionary on the sample files, each of which should hold one
+message, and prints the total compressed size of the samples with and without it.
+Data compressed with a dictionary is not standard Snappy, and can only be
+decompressed by rsnappy with the same dictionary.
+
+Measuring with the samples the dictionary was trained on overestimates the gain.
+Use -D to measure a dictionary on other samples.
+
+Options:
+  -h, --help               Show this message.
+  -s, --size=<bytes>       Maximum dictionary size (default 16384, at most 32768).
+  -o, --output=<file>      Write the dictionary to <file>.
+  -D, --dictionary=<file>  Measure an existing dictionary instead of training one.
+";
+
+const DEFAULT_SIZE: usize = 16 * 1024;
+
+struct DictArgs {
+    samples: Vec<String>,
+    size: usize,
+    output: Option<String>,
+    dictionary: Option<String>,
+}
+
+fn parse_args(argv: &[String]) -> Result<DictArgs, String> {
+    let mut args = DictArgs {
+        samples: Vec::new(),
+        size: DEFAULT_SIZE,
+        output: None,
+        dictionary: None,
+    };
+    let mut i = 0;
+    while i < argv.len() {
+        let arg = &argv[i];
+        i += 1;
+        if !arg.starts_with("-") {
+            args.samples.push(arg.clone());
+            continue;
+        }
+        let (name, inline) = match arg.find('=') {
+            Some(eq) => (&arg[..eq], Some(arg[eq + 1..].to_string())),
+            None     => (&arg[..], None)
+        };
+        let value = match inline {
+            Some(v) => v,
+            None if i < argv.len() => { i += 1; argv[i - 1].clone() },
+            None => return Err(format!("option '{}' requires a value", name))
+        };
+        match name {
+            "-s" | "--size"       => {
+                args.size = match value.parse::<usize>() {
+                    Ok(n) if n <= MAX_DICTIONARY_SIZE => n,
+                    _ => return Err(format!("invalid dictionary size '{}', the largest is {}", value, MAX_DICTIONARY_SIZE))
+                };
+            },
+            "-o" | "--output"     => args.output = Some(value),
+            "-D" | "--dictionary" => args.dictionary = Some(value),
+            _ => return Err(format!("unknown option '{}'", name))
+        }
+    }
+    if args.dictionary.is_some() && args.output.is_some() {
+        return Err("-o can not be used with -D".to_string());
+    }
+    if args.samples.is_empty() {
+        return Err("no samples".to_string());
+    }
+    Ok(args)
+}
+
+fn read_file(name: &str) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    try!(try!(File::open(name)).read_to_end(&mut data));
+    Ok(data)
+}
+
+fn compressed_size(sample: &[u8], dictionary: Option<&[u8]>) -> io::Result<usize> {
+    let mut out = Vec::new();
+    match dictionary {
+        Some(d) => try!(compress_with_dictionary(&mut Cursor::new(sample), &mut out, d, &Default::default())),
+        None    => try!(compress(&mut Cursor::new(sample), &mut out))
+    }
+    Ok(out.len())
+}
+
+fn ratio(uncompressed: usize, compressed: usize) -> f64 {
+    if compressed == 0 { 0.0 } else { uncompressed as f64 / compressed as f64 }
+}
+
+fn run(args: &DictArgs) -> Result<(), String> {
+    let mut samples = Vec::new();
+    for name in args.samples.iter() {
+        samples.push(try!(read_file(name).map_err(|e| format!("{}: {}", name, e))));
+    }
+    let dictionary = match args.dictionary {
+        Some(ref name) => {
+            let d = try!(read_file(name).map_err(|e| format!("{}: {}", name, e)));
+            if d.len() > MAX_DICTIONARY_SIZE {
+                return Err(format!("{}: dictionary larger than {} bytes", name, MAX_DICTIONARY_SIZE));
+            }
+            d
+        },
+        None => {
+            let refs: Vec<&[u8]> = samples.iter().map(|s| &s[..]).collect();
+            train_dictionary(&refs[..], args.size)
+        }
+    };
+    if let Some(ref name) = args.output {
+        try!(File::create(name).and_then(|mut f| f.write_all(&dictionary[..])).map_err(|e| format!("{}: {}", name, e)));
+    }
+
+    let total: usize = samples.iter().map(|s| s.len()).sum();
+    let (mut plain, mut with_dictionary) = (0, 0);
+    for sample in samples.iter() {
+        plain += try!(compressed_size(&sample[..], None).map_err(|e| e.to_string()));
+        with_dictionary += try!(compressed_size(&sample[..], Some(&dictionary[..])).map_err(|e| e.to_string()));
+    }
+    println!("{:<20} {:>10} bytes in {} samples", "uncompressed", total, samples.len());
+    println!("{:<20} {:>10} bytes", "dictionary", dictionary.len());
+    println!("{:<20} {:>10} bytes  ratio {:.3}", "without dictionary", plain, ratio(total, plain));
+    println!("{:<20} {:>10} bytes  ratio {:.3}", "with dictionary", with_dictionary, ratio(total, with_dictionary));
+    Ok(())
+}
+
+/// Runs the subcommand, returning the exit code.
+pub fn main(argv: &[String]) -> i32 {
+    if argv.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE.trim());
+        return 0;
+    }
+    let args = match parse_args(argv) {
+        Ok(args) => args,
+        Err(msg) => {
+            let _ = writeln!(io::stderr(), "rsnap dict: {}\nTry 'rsnap dict --help' for more information.", msg);
+            return 2;
+        }
+    };
+    match run(&args) {
+        Ok(())   => 0,
+        Err(msg) => {
+            let _ = writeln!(io::stderr(), "rsnap dict: {}", msg);
+            1
+        }
+    }
+}
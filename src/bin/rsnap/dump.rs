@@ -0,0 +1,143 @@
+//! `rsnap dump`: lists the tags of a raw stream, for debugging.
+
+use std::ascii;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use rsnappy::{disassemble, CopyKind, Tag};
+
+static USAGE: &'static str = "
+Usage: rsnap dump [options] [<file>]
+
+Prints the tags of a raw Snappy stream, one per line: where the tag starts in the
+input, where its output starts, and the length and offset of copies or the first
+bytes of literals. Reads standard input if there is no file, or it is -.
+
+Problems are marked in the listing, and make the exit code 1: copies from before
+the start of the output, a stream that is cut off, or an output length that does
+not match the one at the start of the stream.
+
+Options:
+  -h, --help     Show this message.
+";
+
+/// How many bytes of each literal to show.
+const LITERAL_PREVIEW: usize = 16;
+
+fn read_input(name: &str) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if name == "-" {
+        let stdin = io::stdin();
+        try!(stdin.lock().read_to_end(&mut data));
+    } else {
+        try!(try!(File::open(name)).read_to_end(&mut data));
+    }
+    Ok(data)
+}
+
+fn preview(data: &[u8]) -> String {
+    let mut s = String::from("\"");
+    for &c in data.iter().take(LITERAL_PREVIEW) {
+        if c == b' ' || c.is_ascii_graphic() && c != b'"' && c != b'\\' {
+            s.push(c as char);
+        } else {
+            s.extend(ascii::escape_default(c).map(|e| e as char));
+        }
+    }
+    s.push('"');
+    if data.len() > LITERAL_PREVIEW {
+        s.push_str("...");
+    }
+    s
+}
+
+/// Writes the listing, and returns whether the stream is valid.
+fn dump<W: Write>(input: &[u8], out: &mut W) -> io::Result<bool> {
+    let mut tags = disassemble(input);
+    let length = match tags.uncompressed_length() {
+        Some(n) => n,
+        None    => {
+            try!(writeln!(out, "error: unterminated uncompressed length"));
+            return Ok(false);
+        }
+    };
+    try!(writeln!(out, "uncompressed length {}", length));
+    try!(writeln!(out, "{:>10} {:>10}  tag", "input", "output"));
+    let (mut output, mut literals, mut copies) = (0u64, 0, 0);
+    let mut valid = true;
+    loop {
+        let pos = tags.position();
+        let tag = match tags.next() {
+            None         => break,
+            Some(Ok(t))  => t,
+            Some(Err(e)) => {
+                try!(writeln!(out, "{:>10} {:>10}  error: {}", pos, output, io::Error::from(e)));
+                valid = false;
+                break;
+            }
+        };
+        match tag {
+            Tag::Literal { len, pos: data } => {
+                literals += 1;
+                try!(writeln!(out, "{:>10} {:>10}  literal  len {:<5}  {}",
+                              pos, output, len, preview(&input[data..data + len as usize])));
+            },
+            Tag::Copy { offset, len, tag_kind } => {
+                copies += 1;
+                let name = match tag_kind {
+                    CopyKind::OneByteOffset  => "copy-1",
+                    CopyKind::TwoByteOffset  => "copy-2",
+                    CopyKind::FourByteOffset => "copy-4",
+                };
+                try!(write!(out, "{:>10} {:>10}  {:<7}  len {:<5}  offset {}", pos, output, name, len, offset));
+                if offset == 0 || offset as u64 > output {
+                    try!(write!(out, "  error: copy offset before start of output"));
+                    valid = false;
+                }
+                try!(writeln!(out));
+            }
+        }
+        output += tag.output_len() as u64;
+    }
+    try!(writeln!(out, "{} tags: {} literals, {} copies, {} bytes of output", literals + copies, literals, copies, output));
+    if output != length as u64 {
+        try!(writeln!(out, "error: output length {} does not match uncompressed length {}", output, length));
+        valid = false;
+    }
+    Ok(valid)
+}
+
+/// Runs the subcommand, returning the exit code.
+pub fn main(argv: &[String]) -> i32 {
+    if argv.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE.trim());
+        return 0;
+    }
+    let name = match argv.len() {
+        0 => "-",
+        1 if argv[0] == "-" || !argv[0].starts_with("-") => &argv[0][..],
+        _ => {
+            let _ = writeln!(io::stderr(), "rsnap dump: expected one file\nTry 'rsnap dump --help' for more information.");
+            return 2;
+        }
+    };
+    let input = match read_input(name) {
+        Ok(data) => data,
+        Err(e)   => {
+            let _ = writeln!(io::stderr(), "rsnap dump: {}: {}", name, e);
+            return 1;
+        }
+    };
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    match dump(&input[..], &mut out).and_then(|valid| out.flush().map(|_| valid)) {
+        Ok(true)  => 0,
+        Ok(false) => 1,
+        // The reader, like head, has seen enough
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "rsnap dump: {}", e);
+            1
+        }
+    }
+}
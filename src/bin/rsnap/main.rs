@@ -0,0 +1,419 @@
+extern crate rsnappy;
+
+mod bench;
+mod dict;
+mod dump;
+mod stats;
+
+use std::env;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, BufWriter, Cursor, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use rsnappy::{compress_with_options, compress_with_stats, stream_info, AutoDecoder, CompressorOptions, Format, FramedWriter,
+              HadoopWriter, StreamInfo, XerialWriter, MAX_BLOCK_SIZE};
+
+static USAGE: &'static str = "
+Usage: rsnap [options] [<file>...]
+       rsnap bench [options] <file>...
+       rsnap dict [options] <sample>...
+       rsnap dump [<file>]
+       rsnap --help
+
+Compresses each file to <file>.sz and removes the original, or with -d decompresses
+each <file>.sz. With no files, or when a file is -, reads standard input and writes
+standard output.
+
+rsnap bench measures compression ratio and speed, see rsnap bench --help.
+rsnap dict trains a preset dictionary for small messages, see rsnap dict --help.
+rsnap dump lists the tags of a raw stream, see rsnap dump --help.
+
+Options:
+  -h, --help             Show this message.
+  -d, --decompress       Decompress. The input format is detected automatically.
+  -c, --stdout           Write to standard output and keep the input files.
+  -k, --keep             Keep the input files.
+  -f, --force            Overwrite existing output files, and write compressed data
+                         to a terminal.
+  -t, --test             Check the integrity of compressed files, including
+                         checksums and lengths, without writing any output.
+  -l, --list             Print the sizes, compression ratio, number of chunks
+                         and detected format of compressed files.
+  -F, --format=<fmt>     Format to compress to: framed (the default), raw, hadoop
+                         or xerial.
+  -b, --block-size=<kb>  Sets compressor block size, for the raw format only.
+                         There is no simple relationship between block size and
+                         performance or compressed size.
+      --stats            Compress each file to the raw format without writing
+                         any output, and print the number of literals and copies,
+                         histograms of match lengths and offsets, and the sizes of
+                         the compressed blocks. Takes -b into account.
+";
+
+const SUFFIX: &'static str = ".sz";
+
+struct Args {
+    files: Vec<String>,
+    decompress: bool,
+    stdout: bool,
+    keep: bool,
+    force: bool,
+    test: bool,
+    list: bool,
+    stats: bool,
+    format: Format,
+    block_size: Option<u16>,
+}
+
+fn parse_format(s: &str) -> Result<Format, String> {
+    match s {
+        "framed" => Ok(Format::Framed),
+        "raw"    => Ok(Format::Raw),
+        "hadoop" => Ok(Format::Hadoop),
+        "xerial" => Ok(Format::Xerial),
+        _        => Err(format!("unknown format '{}'", s))
+    }
+}
+
+fn parse_block_size(s: &str) -> Result<u16, String> {
+    let kb = try!(s.parse::<usize>().map_err(|_| format!("invalid block size '{}'", s)));
+    match kb.checked_mul(1024) {
+        Some(b) if b <= MAX_BLOCK_SIZE => Ok(b as u16),
+        _ => Err(format!("chosen block size {}kb is greater than the maximum {}kb", kb, MAX_BLOCK_SIZE / 1024))
+    }
+}
+
+/// Parses the command line like getopt: short flags can be combined (`-dc`), and option
+/// values can be given as `-b64`, `-b 64`, `--block-size=64` or `--block-size 64`.
+fn parse_args(argv: &[String]) -> Result<Args, String> {
+    let mut args = Args {
+        files: Vec::new(),
+        decompress: false,
+        stdout: false,
+        keep: false,
+        force: false,
+        test: false,
+        list: false,
+        stats: false,
+        format: Format::Framed,
+        block_size: None,
+    };
+    let mut i = 0;
+    let mut only_files = false;
+    while i < argv.len() {
+        let arg = &argv[i];
+        i += 1;
+        if only_files || arg == "-" || !arg.starts_with("-") {
+            args.files.push(arg.clone());
+            continue;
+        }
+        if arg == "--" {
+            only_files = true;
+            continue;
+        }
+        // Split into (name, inline value) pairs, expanding combined short flags.
+        let mut opts = Vec::new();
+        if arg.starts_with("--") {
+            match arg.find('=') {
+                Some(eq) => opts.push((arg[..eq].to_string(), Some(arg[eq + 1..].to_string()))),
+                None     => opts.push((arg.clone(), None))
+            }
+        } else {
+            for (pos, c) in arg[1..].char_indices() {
+                let name = format!("-{}", c);
+                let rest = &arg[1 + pos + c.len_utf8()..];
+                if (c == 'F' || c == 'b') && !rest.is_empty() {
+                    opts.push((name, Some(rest.to_string())));
+                    break;
+                }
+                opts.push((name, None));
+            }
+        }
+        for (name, value) in opts {
+            let takes_value = match &name[..] {
+                "-F" | "--format" | "-b" | "--block-size" => true,
+                _ => false
+            };
+            let value = if takes_value && value.is_none() {
+                if i == argv.len() {
+                    return Err(format!("option '{}' requires a value", name));
+                }
+                i += 1;
+                Some(argv[i - 1].clone())
+            } else if !takes_value && value.is_some() {
+                return Err(format!("option '{}' does not take a value", name));
+            } else {
+                value
+            };
+            match &name[..] {
+                "-h" | "--help"       => {
+                    println!("{}", USAGE.trim());
+                    exit(0);
+                },
+                "-d" | "--decompress" => args.decompress = true,
+                "-c" | "--stdout"     => args.stdout = true,
+                "-k" | "--keep"       => args.keep = true,
+                "-f" | "--force"      => args.force = true,
+                "-t" | "--test"       => args.test = true,
+                "-l" | "--list"       => args.list = true,
+                "--stats"             => args.stats = true,
+                "-F" | "--format"     => args.format = try!(parse_format(&value.unwrap())),
+                "-b" | "--block-size" => args.block_size = Some(try!(parse_block_size(&value.unwrap()))),
+                _ => return Err(format!("unknown option '{}'", name))
+            }
+        }
+    }
+    if args.block_size.is_some() && args.format != Format::Raw && !args.stats {
+        return Err("--block-size only applies to --format=raw".to_string());
+    }
+    if args.files.is_empty() {
+        args.files.push("-".to_string());
+    }
+    Ok(args)
+}
+
+fn compress_stream<R: Read, W: Write>(args: &Args, mut inp: R, out: W) -> io::Result<()> {
+    match args.format {
+        Format::Framed => {
+            let mut writer = FramedWriter::new(out);
+            try!(io::copy(&mut inp, &mut writer));
+            try!(writer.into_inner()).flush()
+        },
+        Format::Hadoop => {
+            let mut writer = HadoopWriter::new(out);
+            try!(io::copy(&mut inp, &mut writer));
+            try!(writer.into_inner()).flush()
+        },
+        Format::Xerial => {
+            let mut writer = XerialWriter::new(out);
+            try!(io::copy(&mut inp, &mut writer));
+            try!(writer.into_inner()).flush()
+        },
+        Format::Raw => {
+            // The raw format starts with the uncompressed length, so we need all of the input.
+            let mut input = Vec::new();
+            try!(inp.read_to_end(&mut input));
+            if input.len() > ::std::u32::MAX as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "input too large for the raw format"));
+            }
+            let mut options = CompressorOptions::default();
+            if let Some(b) = args.block_size {
+                options.block_size = b;
+            }
+            let mut out = out;
+            try!(compress_with_options(&mut Cursor::new(&input[..]), &mut out, &options));
+            out.flush()
+        }
+    }
+}
+
+fn decompress_stream<R: Read, W: Write>(inp: R, mut out: W) -> io::Result<()> {
+    let mut decoder = try!(AutoDecoder::new(inp));
+    try!(io::copy(&mut decoder, &mut out));
+    out.flush()
+}
+
+fn run<R: Read, W: Write>(args: &Args, inp: R, out: W) -> io::Result<()> {
+    if args.decompress {
+        decompress_stream(inp, out)
+    } else {
+        compress_stream(args, inp, out)
+    }
+}
+
+/// Runs `f` on the named file, or on standard input for -.
+fn with_input<T, F: FnOnce(&mut dyn Read) -> io::Result<T>>(name: &str, f: F) -> Result<T, String> {
+    let result = if name == "-" {
+        let stdin = io::stdin();
+        let mut inp = stdin.lock();
+        f(&mut inp)
+    } else {
+        match File::open(name) {
+            Ok(file) => f(&mut BufReader::new(file)),
+            Err(e)   => Err(e)
+        }
+    };
+    result.map_err(|e| e.to_string())
+}
+
+fn test_file(name: &str) -> Result<(), String> {
+    with_input(name, |inp| decompress_stream(inp, io::sink()))
+}
+
+fn print_info(info: &StreamInfo, name: &str) {
+    let ratio = if info.uncompressed_len == 0 {
+        0.0
+    } else {
+        100.0 * (1.0 - info.compressed_len as f64 / info.uncompressed_len as f64)
+    };
+    println!("{:>12} {:>12} {:>6.1}% {:>7}  {:<6}  {}",
+             info.compressed_len, info.uncompressed_len, ratio, info.chunks, info.format, name);
+}
+
+/// Lists all files like gzip -l, with a total if there is more than one.
+fn list_files(files: &[String]) -> bool {
+    let mut failed = false;
+    let mut total: Option<StreamInfo> = None;
+    println!("{:>12} {:>12} {:>7} {:>7}  {:<6}  {}", "compressed", "uncompressed", "ratio", "chunks", "format", "name");
+    for name in files.iter() {
+        match with_input(name, |inp| stream_info(inp)) {
+            Ok(info) => {
+                print_info(&info, name);
+                total = Some(match total {
+                    None    => info,
+                    Some(t) => StreamInfo {
+                        format: t.format,
+                        compressed_len: t.compressed_len + info.compressed_len,
+                        uncompressed_len: t.uncompressed_len + info.uncompressed_len,
+                        chunks: t.chunks + info.chunks,
+                    }
+                });
+            },
+            Err(msg) => {
+                let _ = writeln!(io::stderr(), "rsnap: {}: {}", name, msg);
+                failed = true;
+            }
+        }
+    }
+    if let Some(t) = total {
+        if files.len() > 1 {
+            print_info(&t, "(totals)");
+        }
+    }
+    failed
+}
+
+/// Prints compression statistics for all files.
+fn stats_files(args: &Args) -> bool {
+    let mut options = CompressorOptions::default();
+    if let Some(b) = args.block_size {
+        options.block_size = b;
+    }
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut failed = false;
+    for name in args.files.iter() {
+        let result = with_input(name, |inp| {
+            let mut input = Vec::new();
+            try!(inp.read_to_end(&mut input));
+            let mut compressed = Vec::new();
+            let stats = try!(compress_with_stats(&mut Cursor::new(&input[..]), &mut compressed, &options));
+            Ok((stats, compressed.len()))
+        });
+        let (s, compressed_len) = match result {
+            Ok(r) => r,
+            Err(msg) => {
+                let _ = writeln!(io::stderr(), "rsnap: {}: {}", name, msg);
+                failed = true;
+                continue;
+            }
+        };
+        // Flushed for each file, so that errors on stderr show up in order
+        match stats::print_stats(&mut out, name, &s, compressed_len).and_then(|_| out.flush()) {
+            Ok(()) => {},
+            // The reader, like head, has seen enough
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return failed,
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "rsnap: {}", e);
+                return true;
+            }
+        }
+    }
+    failed
+}
+
+fn output_path(args: &Args, path: &Path) -> Result<PathBuf, String> {
+    let name = path.to_string_lossy();
+    if args.decompress {
+        if !name.ends_with(SUFFIX) || name.len() == SUFFIX.len() {
+            return Err("unknown suffix -- ignored".to_string());
+        }
+        Ok(PathBuf::from(&name[..name.len() - SUFFIX.len()]))
+    } else {
+        if name.ends_with(SUFFIX) && !args.force {
+            return Err(format!("already has {} suffix -- unchanged", SUFFIX));
+        }
+        Ok(PathBuf::from(format!("{}{}", name, SUFFIX)))
+    }
+}
+
+fn process_file(args: &Args, name: &str) -> Result<(), String> {
+    let stdout = io::stdout();
+    if !args.decompress && !args.force && (name == "-" || args.stdout) && stdout.is_terminal() {
+        return Err("compressed data not written to a terminal. Use -f to force compression.".to_string());
+    }
+    if name == "-" {
+        let stdin = io::stdin();
+        return run(args, stdin.lock(), BufWriter::new(stdout.lock())).map_err(|e| e.to_string());
+    }
+
+    let path = Path::new(name);
+    let file = try!(File::open(path).map_err(|e| e.to_string()));
+    let metadata = try!(file.metadata().map_err(|e| e.to_string()));
+    if !metadata.is_file() {
+        return Err("not a regular file -- ignored".to_string());
+    }
+    let inp = BufReader::new(file);
+    if args.stdout {
+        return run(args, inp, BufWriter::new(stdout.lock())).map_err(|e| e.to_string());
+    }
+
+    let out_path = try!(output_path(args, path));
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if args.force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let out = match options.open(&out_path) {
+        Ok(f)  => f,
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(format!("{} already exists. Use -f to overwrite.", out_path.display()));
+        },
+        Err(e) => return Err(format!("{}: {}", out_path.display(), e))
+    };
+    if let Err(e) = run(args, inp, BufWriter::new(out)) {
+        let _ = fs::remove_file(&out_path);
+        return Err(e.to_string());
+    }
+    let _ = fs::set_permissions(&out_path, metadata.permissions());
+    if !args.keep {
+        try!(fs::remove_file(path).map_err(|e| e.to_string()));
+    }
+    Ok(())
+}
+
+fn main() {
+    let argv: Vec<String> = env::args().skip(1).collect();
+    match argv.first().map(|a| &a[..]) {
+        Some("bench") => exit(bench::main(&argv[1..])),
+        Some("dict")  => exit(dict::main(&argv[1..])),
+        Some("dump")  => exit(dump::main(&argv[1..])),
+        _             => { }
+    }
+    let args = match parse_args(&argv[..]) {
+        Ok(args) => args,
+        Err(msg) => {
+            let _ = writeln!(io::stderr(), "rsnap: {}\nTry 'rsnap --help' for more information.", msg);
+            exit(2);
+        }
+    };
+    if args.list {
+        exit(if list_files(&args.files[..]) { 1 } else { 0 });
+    }
+    if args.stats {
+        exit(if stats_files(&args) { 1 } else { 0 });
+    }
+    let mut failed = false;
+    for name in args.files.iter() {
+        let result = if args.test { test_file(name) } else { process_file(&args, name) };
+        if let Err(msg) = result {
+            let _ = writeln!(io::stderr(), "rsnap: {}: {}", name, msg);
+            failed = true;
+        }
+    }
+    exit(if failed { 1 } else { 0 });
+}
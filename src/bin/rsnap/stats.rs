@@ -0,0 +1,45 @@
+//! `rsnap --stats`: reports what the compressor does with each file.
+
+use std::io;
+use std::io::Write;
+use rsnappy::CompressionStats;
+
+/// The range of values in power of two bucket `i`.
+fn bucket(i: usize) -> String {
+    let (lo, hi) = (1u64 << i, (1u64 << (i + 1)) - 1);
+    if lo == hi { lo.to_string() } else { format!("{}-{}", lo, hi) }
+}
+
+fn print_histogram<W: Write>(out: &mut W, title: &str, buckets: &[u64]) -> io::Result<()> {
+    let total: u64 = buckets.iter().sum();
+    if total == 0 {
+        return Ok(());
+    }
+    try!(writeln!(out, "  {:<22} {:>12}", title, "matches"));
+    for (i, &n) in buckets.iter().enumerate().filter(|&(_, &n)| n > 0) {
+        try!(writeln!(out, "  {:>22} {:>12} {:>6.1}%", bucket(i), n, 100.0 * n as f64 / total as f64));
+    }
+    Ok(())
+}
+
+pub fn print_stats<W: Write>(out: &mut W, name: &str, stats: &CompressionStats, compressed_len: usize)
+                             -> io::Result<()> {
+    let uncompressed: usize = stats.blocks.iter().map(|b| b.0).sum();
+    let ratio = if compressed_len == 0 { 0.0 } else { uncompressed as f64 / compressed_len as f64 };
+    try!(writeln!(out, "{}: {} -> {} bytes, ratio {:.3}, {} blocks of up to {} bytes",
+                  name, uncompressed, compressed_len, ratio, stats.blocks.len(), stats.block_size));
+    try!(writeln!(out, "  {:<22} {:>12} tags {:>12} bytes", "literals", stats.literals, stats.literal_bytes));
+    try!(writeln!(out, "  {:<22} {:>12} tags", "copies, 1 byte offset", stats.copies_1_byte_offset));
+    try!(writeln!(out, "  {:<22} {:>12} tags", "copies, 2 byte offset", stats.copies_2_byte_offset));
+    try!(writeln!(out, "  {:<22} {:>12} tags", "copies, 4 byte offset", stats.copies_4_byte_offset));
+    try!(writeln!(out, "  {:<22} {:>12}", "hash collisions", stats.hash_collisions));
+    try!(print_histogram(out, "match length", &stats.match_lengths));
+    try!(print_histogram(out, "match offset", &stats.match_offsets));
+    if !stats.blocks.is_empty() {
+        let sizes = stats.blocks.iter().map(|b| b.1);
+        let mean = sizes.clone().sum::<usize>() as f64 / stats.blocks.len() as f64;
+        try!(writeln!(out, "  {:<22} {:>12} min {:>12.1} mean {:>12} max", "compressed block bytes",
+                      sizes.clone().min().unwrap(), mean, sizes.max().unwrap()));
+    }
+    Ok(())
+}
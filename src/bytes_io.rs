@@ -0,0 +1,89 @@
+//! Compression to and from `bytes` buffers, with the `bytes` feature.
+
+use std::cmp;
+use std::io::{self, Cursor};
+use bytes::buf::Writer;
+use bytes::{BufMut, Bytes, BytesMut};
+use compress::{compress, max_compressed_length};
+use decompress::{check_copy_offset, decompress, SnappyError, SnappyWrite, MAX_RESERVE};
+
+/// Compresses `input` into a `Bytes`, without copying the output.
+pub fn compress_to_bytes(input: &[u8]) -> Bytes {
+    let mut out = BytesMut::with_capacity(max_compressed_length(input.len())).writer();
+    compress(&mut Cursor::new(input), &mut out).expect("writing to BytesMut does not fail");
+    out.into_inner().freeze()
+}
+
+/// Decompresses `input` into a `Bytes`, without copying the output.
+///
+/// To decompress into a `BytesMut` of your own, wrap it with `BufMut::writer`.
+pub fn decompress_to_bytes(input: &[u8]) -> Result<Bytes, SnappyError> {
+    let mut out = BytesMut::new().writer();
+    try!(decompress(&mut Cursor::new(input), &mut out));
+    Ok(out.into_inner().freeze())
+}
+
+/// `BytesMut` does not implement `Write` itself, so this is implemented for the writer that
+/// `BufMut::writer` wraps it in, not for `BytesMut`. To decompress into a `BytesMut`, pass
+/// `buf.writer()` and take the buffer back with `into_inner`.
+impl SnappyWrite for Writer<BytesMut> {
+    fn write_from_self(&mut self, offset: u32, len: u8) -> io::Result<()> {
+        let buf = self.get_mut();
+        try!(check_copy_offset(offset, buf.len()));
+        let start = buf.len() - offset as usize;
+        let offset = offset as usize;
+        // A copy may overlap its own output, so build it up before appending it
+        let mut copy = [0; 255];
+        for i in 0..len as usize {
+            copy[i] = if i < offset { buf[start + i] } else { copy[i - offset] };
+        }
+        buf.extend_from_slice(&copy[..len as usize]);
+        Ok(())
+    }
+
+    fn set_uncompressed_length(&mut self, length: u32) {
+        self.get_mut().reserve(cmp::min(length as usize, MAX_RESERVE));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{BufMut, BytesMut};
+    use std::io::Cursor;
+    use compress::compress;
+    use decompress::{decompress, SnappyWrite, MAX_RESERVE};
+    use tags::StreamBuilder;
+    use super::{compress_to_bytes, decompress_to_bytes};
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let text = include_bytes!("../tests/moonstone-short.txt");
+        let compressed = compress_to_bytes(&text[..]);
+        let mut expected = Vec::new();
+        compress(&mut Cursor::new(&text[..]), &mut expected).unwrap();
+        assert!(&compressed[..] == &expected[..]);
+        let decompressed = decompress_to_bytes(&compressed[..]).unwrap();
+        assert!(&decompressed[..] == &text[..]);
+    }
+
+    #[test]
+    fn test_bytes_overlapping_copy() {
+        let input = StreamBuilder::new(7).literal(&[1, 2, 3]).copy(2, 4).build();
+        let mut out = BytesMut::new().writer();
+        decompress(&mut Cursor::new(&input[..]), &mut out).unwrap();
+        assert_eq!(&out.get_ref()[..], &[1, 2, 3, 2, 3, 2, 3]);
+    }
+
+    #[test]
+    fn test_bytes_bad_offset() {
+        let input = StreamBuilder::new(5).literal(&[1]).copy(2, 4).build();
+        assert!(decompress_to_bytes(&input[..]).is_err());
+    }
+
+    #[test]
+    fn test_bytes_reserve_capped() {
+        let mut out = BytesMut::new().writer();
+        out.set_uncompressed_length(0xFFFF_FFFF);
+        assert!(out.get_ref().capacity() <= MAX_RESERVE);
+    }
+}
@@ -2,30 +2,51 @@ use std::default::Default;
 use std::io::{BufReader, BufRead, Write};
 use std::io;
 use std::cmp;
-use std::ptr;
+use std::convert::TryInto;
 use std::slice::Iter;
 use std::fs::File;
 use util::next_power_of_2;
+use decompress::SnappyError;
+#[cfg(not(feature = "safe"))]
 use zero_array::ZeroArray;
+#[cfg(not(feature = "safe"))]
+use match_length::find_match_length;
+#[cfg(feature = "safe")]
+use match_length::find_match_length_safe;
 
 const LITERAL: u8 = 0;
 const COPY_1_BYTE: u8 = 1;
 const COPY_2_BYTE: u8 = 2;
+const COPY_4_BYTE: u8 = 3;
 
 const MIN_COPY_LEN: u16 = 4;
 const MAX_COPY_LEN: u16 = 64;
 
+/// Largest offset of copies with a 2 byte offset. Copies further back need 4 bytes for the offset.
+const MAX_COPY_2_BYTE_OFFSET: u32 = 0xFFFF;
+
+/// Shortest copy with a 4 byte offset worth emitting. The tag takes 5 bytes,
+/// so shorter copies would save little or nothing over a literal.
+const MIN_COPY_4_BYTE_LEN: u16 = 8;
+
+/// Largest `CompressorOptions::window_size`.
+pub const MAX_WINDOW_SIZE: u32 = 1 << 30;
+
 const BLOCK_MARGIN: usize = 16;
 
 pub const MAX_BLOCK_SIZE: usize = ::std::u16::MAX as usize;
 
+/// Smaller block sizes are rounded up to this, since every block costs at least one tag byte.
+pub const MIN_BLOCK_SIZE: usize = BLOCK_MARGIN;
+
 /// Maximum number of positions stored for one prefix. Must not be 0.
 /// Larger values leads to better compression, but worsens compression speed and memory usage.
 const MAX_CHAIN_LEN: u8 = 3;
 
 const MAX_HASHTABLE_BITS: u32 = 14;
 
-const MAX_HASHTABLE_SIZE: u32 = 1 << MAX_HASHTABLE_BITS;
+/// With a window, positions are kept much longer, so a larger table pays off.
+const MAX_WINDOW_HASHTABLE_BITS: u32 = 18;
 
 pub trait SnappyRead : BufRead {
     /// Returns the total number of bytes left to be read.
@@ -53,14 +74,13 @@ impl SnappyRead for BufReader<File> {
 
 /// Small, fixed-size, non-allocating queue of positions of prefixes in the Dict.
 /// When a new element is added, the oldest is removed.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 struct PositionQueue {
-    queue: [u16; MAX_CHAIN_LEN as usize],
+    queue: [u32; MAX_CHAIN_LEN as usize],
     len: u8
 }
 
 impl PositionQueue {
-    #[allow(dead_code)]
     fn new() -> PositionQueue {
         PositionQueue {
             queue: [0; MAX_CHAIN_LEN as usize],
@@ -68,11 +88,11 @@ impl PositionQueue {
         }
     }
 
-    fn iter<'a>(&'a self) -> Iter<'a, u16> {
+    fn iter<'a>(&'a self) -> Iter<'a, u32> {
         self.queue[..self.len()].iter()
     }
 
-    fn push(&mut self, pos: u16) {
+    fn push(&mut self, pos: u32) {
         if self.len > 0 && MAX_CHAIN_LEN > 1 {
             if MAX_CHAIN_LEN == 2 {
                 self.queue[1] = self.queue[0];
@@ -90,9 +110,7 @@ impl PositionQueue {
                 self.queue[1] = self.queue[0];
             } else {
                 let len = cmp::min(self.len + 1, MAX_CHAIN_LEN) as usize;
-                unsafe {
-                    ptr::copy(self.queue.as_ptr(), self.queue.as_mut_ptr().offset(1), len - 1)
-                }
+                self.queue.copy_within(0..len - 1, 1);
             }
         }
         self.queue[0] = pos;
@@ -103,41 +121,84 @@ impl PositionQueue {
 }
 
 pub struct CompressorOptions {
+    /// The input is compressed in blocks of this size. Sizes below 16 are treated as 16.
     pub block_size: u16,
+    /// How far back copies may reach, in bytes, at most `MAX_WINDOW_SIZE`.
+    ///
+    /// With the default of 0, blocks are compressed independently, like the reference
+    /// implementation does. A window lets copies refer to earlier blocks, using 4 byte offsets
+    /// beyond 64 KiB, which helps with large inputs that repeat themselves over long distances.
+    /// The compressor keeps up to twice the window in memory. Decompressing with
+    /// `decompress_with_window` needs a window at least as large as the larger of this and 64 KiB.
+    ///
+    /// The window also limits copies within a block, so a window smaller than `block_size`
+    /// compresses worse than no window at all. Use windows well above 64 KiB.
+    pub window_size: u32,
+}
+
+impl CompressorOptions {
+    /// Fails with `SnappyError::InvalidOptions` if the window size is larger than `MAX_WINDOW_SIZE`.
+    pub fn check(&self) -> Result<(), SnappyError> {
+        if self.window_size > MAX_WINDOW_SIZE {
+            return Err(SnappyError::InvalidOptions("window size larger than MAX_WINDOW_SIZE"));
+        }
+        Ok(())
+    }
 }
 
 impl Default for CompressorOptions {
     fn default() -> CompressorOptions {
         CompressorOptions {
             block_size: MAX_BLOCK_SIZE as u16,
+            window_size: 0,
         }
     }
 }
 
+#[cfg(not(feature = "safe"))]
+type Table = ZeroArray<(u32, PositionQueue)>;
+
+#[cfg(feature = "safe")]
+type Table = Vec<(u32, PositionQueue)>;
+
+#[cfg(not(feature = "safe"))]
+fn new_table(capacity: u32) -> Table {
+    // All zeroes is a valid (u32, PositionQueue)
+    unsafe { ZeroArray::new(capacity) }
+}
+
+#[cfg(feature = "safe")]
+fn new_table(capacity: u32) -> Table {
+    vec![Default::default(); capacity as usize]
+}
+
 struct LossyHashTable {
-    table: ZeroArray<(u32, PositionQueue)>,
-    range_shift: u32
+    table: Table,
+    range_shift: u32,
+    /// How often a prefix replaced the positions of another prefix with the same hash.
+    collisions: u64,
 }
 
 impl LossyHashTable {
-    fn new(capacity: u32) -> LossyHashTable {
-        let real_capacity = cmp::min(MAX_HASHTABLE_SIZE, cmp::max(16, next_power_of_2(capacity)));
+    fn new(capacity: u32, max_bits: u32) -> LossyHashTable {
+        let real_capacity = cmp::min(1 << max_bits, cmp::max(16, next_power_of_2(capacity)));
         LossyHashTable {
-            table: unsafe { ZeroArray::new(real_capacity) },
-            range_shift: 32 - real_capacity.trailing_zeros()
+            table: new_table(real_capacity),
+            range_shift: 32 - real_capacity.trailing_zeros(),
+            collisions: 0,
         }
     }
 
-    fn get_or_insert<'a>(&'a mut self, key: &[u8], pos: u16) -> Option<&'a mut PositionQueue> {
+    fn get_or_insert<'a>(&'a mut self, key: &[u8], pos: u32) -> Option<&'a mut PositionQueue> {
         debug_assert_eq!(key.len(), MIN_COPY_LEN as usize);
-        // TODO: What if key.len() < 4 ? (It's not, but still)
-        let key = unsafe { ptr::read(key.as_ptr() as *const u32) };
+        let key = u32::from_ne_bytes(key.try_into().unwrap());
         let idx = self.hash(key);
         // We know idx is always in range, but using safe indexing is for some reason faster.
         let &mut (ref mut stored_key, ref mut queue) = &mut self.table[idx];
         if queue.len() != 0 && *stored_key == key {
             return Some(queue);
         } else {
+            self.collisions += (queue.len() != 0) as u64;
             *stored_key = key;
             queue.len = 0;
             queue.push(pos);
@@ -152,6 +213,20 @@ impl LossyHashTable {
         }
     }
 
+    /// Moves all positions `shift` bytes back, forgetting those that would become negative.
+    fn rebase(&mut self, shift: u32) {
+        for e in self.table.iter_mut() {
+            let mut queue = PositionQueue::new();
+            // Oldest first, to keep the order
+            for &pos in e.1.iter().rev() {
+                if pos >= shift {
+                    queue.push(pos - shift);
+                }
+            }
+            e.1 = queue;
+        }
+    }
+
     // Used for hashing prefixes.
     // A good hash function means better compression, since there will be fewer collisions.
     fn hash(&self, key: u32) -> usize {
@@ -159,64 +234,114 @@ impl LossyHashTable {
     }
 }
 
-struct Dict {
-    table: LossyHashTable
+pub struct Dict {
+    table: LossyHashTable,
+    /// The largest offset of matches that may be returned.
+    max_offset: u32,
 }
 
 impl Dict {
-    fn new(capacity: u32) -> Dict {
+    pub fn new(capacity: u32) -> Dict {
         Dict {
-             table: LossyHashTable::new(capacity)
+             table: LossyHashTable::new(capacity, MAX_HASHTABLE_BITS),
+             max_offset: ::std::u32::MAX,
         }
     }
 
-    fn clear(&mut self) {
+    pub fn with_window(window_size: u32) -> Dict {
+        Dict {
+             table: LossyHashTable::new(window_size, MAX_WINDOW_HASHTABLE_BITS),
+             max_offset: window_size,
+        }
+    }
+
+    pub fn clear(&mut self) {
         self.table.clear();
     }
 
+    pub fn rebase(&mut self, shift: u32) {
+        self.table.rebase(shift);
+    }
+
+    /// How often a prefix replaced another in the hash table, over the life of the `Dict`.
+    pub fn hash_collisions(&self) -> u64 {
+        self.table.collisions
+    }
+
+    /// Adds `start` to the positions of its prefix without looking for a match.
+    fn add(&mut self, block: &[u8], start: usize) {
+        let prefix = &block[start..start + MIN_COPY_LEN as usize];
+        if let Some(positions) = self.table.get_or_insert(prefix, start as u32) {
+            positions.push(start as u32);
+        }
+    }
+
     /// Returns (position, length)
-    fn find_best_match_or_add(&mut self, block: &[u8], start: usize) -> Option<(u16, u16)> {
+    fn find_best_match_or_add(&mut self, block: &[u8], start: usize) -> Option<(u32, u16)> {
         let prefix = &block[start..start + MIN_COPY_LEN as usize];
-        let positions = match self.table.get_or_insert(prefix, start as u16) {
+        let positions = match self.table.get_or_insert(prefix, start as u32) {
             None     => return None,
             Some(ps) => ps
         };
 
-        let mut best_pos;
-        let mut best_len;
-        {
-            let mut posit = positions.iter();
-            best_pos = *posit.next().unwrap();
-            // We already know the first MIN_COPY_LEN bytes are equal, no need to search through them.
-            let search_start = start as u16 + MIN_COPY_LEN;
-            best_len = MIN_COPY_LEN + common_prefix_length(block, best_pos + MIN_COPY_LEN, search_start);
-            for &pos in posit {
-                let len = MIN_COPY_LEN + common_prefix_length(block, pos + MIN_COPY_LEN, search_start);
-                if len > best_len {
-                    best_pos = pos;
-                    best_len = len;
-                }
+        let mut best = None;
+        // We already know the first MIN_COPY_LEN bytes are equal, no need to search through them.
+        let search_start = start + MIN_COPY_LEN as usize;
+        for &pos in positions.iter() {
+            let offset = start as u32 - pos;
+            if offset > self.max_offset {
+                break;  // The rest are even older
+            }
+            let len = MIN_COPY_LEN + common_prefix_length(block, pos as usize + MIN_COPY_LEN as usize, search_start);
+            if offset > MAX_COPY_2_BYTE_OFFSET && len < MIN_COPY_4_BYTE_LEN {
+                continue;
+            }
+            if best.map_or(true, |(_, best_len)| len > best_len) {
+                best = Some((pos, len));
             }
         }
-        positions.push(start as u16);
-        Some((best_pos, best_len))
+        positions.push(start as u32);
+        best
     }
 }
 
 
+/// Returns the largest possible size of the compressed form of `uncompressed_len` bytes,
+/// with any `CompressorOptions`.
+pub fn max_compressed_length(uncompressed_len: usize) -> usize {
+    32 + uncompressed_len + uncompressed_len / 6
+}
+
 pub fn compress<R: SnappyRead, W: Write>(inp: &mut R, out: &mut W) -> io::Result<()> {
     compress_with_options(inp, out, &Default::default())
 }
 
-// TODO Report an error on readers with too much available()
-#[inline(never)]
 pub fn compress_with_options<R: SnappyRead, W: Write>(inp: &mut R, out: &mut W,
                                                    options: &CompressorOptions) -> io::Result<()> {
+    compress_with_history(inp, out, options, &[])
+}
+
+/// Compresses as if the input was preceded by `history`, so that copies may refer to it.
+///
+/// Without a window, only the first block may refer to the history, and blocks are made smaller
+/// if needed to fit the history in `MAX_BLOCK_SIZE`.
+// TODO Report an error on readers with too much available()
+#[inline(never)]
+pub fn compress_with_history<R: SnappyRead, W: Write>(inp: &mut R, out: &mut W, options: &CompressorOptions,
+                                                      history: &[u8]) -> io::Result<()> {
     debug_assert!(inp.available().unwrap() <= ::std::u32::MAX as u64);
+    debug_assert!(history.len() <= MAX_BLOCK_SIZE - MIN_BLOCK_SIZE);
+    try!(options.check());
     let uncompressed_length = try!(inp.available()) as u32;
     try!(write_varint(out, uncompressed_length));
-    let max_block_len = cmp::min(options.block_size as u32, uncompressed_length);
+    if options.window_size > 0 {
+        return compress_with_window(inp, out, options, history);
+    }
+    let block_size = cmp::min(cmp::max(options.block_size as usize, MIN_BLOCK_SIZE), MAX_BLOCK_SIZE - history.len());
+    let max_block_len = cmp::min(block_size as u32, uncompressed_length) + history.len() as u32;
     let mut dict = Dict::new(max_block_len);
+    let mut scratch = Vec::new();
+    let mut tags = Vec::with_capacity(max_compressed_length(block_size));
     let mut written = 0;
     loop {
         let len: usize;
@@ -227,8 +352,20 @@ pub fn compress_with_options<R: SnappyRead, W: Write>(inp: &mut R, out: &mut W,
                 Err(e) => return Err(e)
             };
             len = buf.len();
-            for chunk in buf.chunks(options.block_size as usize) {
-                try!(compress_block(chunk, out, &mut dict));
+            for chunk in buf.chunks(block_size) {
+                if history.is_empty() || written > 0 {
+                    compress_block(chunk, 0, &mut tags, &mut dict);
+                } else {
+                    scratch.clear();
+                    scratch.extend_from_slice(history);
+                    scratch.extend_from_slice(chunk);
+                    for pos in 0..cmp::min(history.len(), scratch.len().saturating_sub(MIN_COPY_LEN as usize - 1)) {
+                        dict.add(&scratch[..], pos);
+                    }
+                    compress_block(&scratch[..], history.len(), &mut tags, &mut dict);
+                }
+                try!(out.write_all(&tags[..]));
+                tags.clear();
                 written += chunk.len() as u32;
                 if written < uncompressed_length {
                     dict.clear();
@@ -239,14 +376,57 @@ pub fn compress_with_options<R: SnappyRead, W: Write>(inp: &mut R, out: &mut W,
     }
 }
 
-fn compress_block<W: Write>(block: &[u8], out: &mut W, dict: &mut Dict)
-                             -> io::Result<()> {
-    if block.len() < BLOCK_MARGIN {  // Too short to bother with copies.
-        return emit_literal(out, block);
+/// Compresses the input in blocks appended to a buffer holding the last `window_size` bytes
+/// (and up to as much again, to not move the contents of the buffer too often).
+fn compress_with_window<R: SnappyRead, W: Write>(inp: &mut R, out: &mut W, options: &CompressorOptions,
+                                                 history: &[u8]) -> io::Result<()> {
+    let block_size = cmp::max(options.block_size as usize, MIN_BLOCK_SIZE);
+    let window_size = options.window_size as usize;
+    let mut dict = Dict::with_window(options.window_size);
+    let mut window = Vec::new();
+    let mut tags = Vec::with_capacity(max_compressed_length(block_size));
+    window.extend_from_slice(history);
+    for pos in 0..history.len().saturating_sub(MIN_COPY_LEN as usize - 1) {
+        dict.add(&window[..], pos);
+    }
+    loop {
+        let len: usize;
+        {
+            let buf = match inp.fill_buf() {
+                Ok(b) if b.len() == 0 => return Ok(()),
+                Ok(b)  => b,
+                Err(e) => return Err(e)
+            };
+            len = buf.len();
+            for chunk in buf.chunks(block_size) {
+                if window.len() > 2 * window_size {
+                    let shift = window.len() - window_size;
+                    window.drain(..shift);
+                    dict.rebase(shift as u32);
+                }
+                let start = window.len();
+                window.extend_from_slice(chunk);
+                compress_block(&window[..], start, &mut tags, &mut dict);
+                try!(out.write_all(&tags[..]));
+                tags.clear();
+            }
+        }
+        inp.consume(len);
+    }
+}
+
+/// Compresses `block[start..]`, appending the tags to `out`. Copies may also refer to
+/// `block[..start]`, which is not emitted.
+///
+/// The tags are collected rather than written one by one, so that the caller can write a whole
+/// block with one `write_all`.
+pub fn compress_block(block: &[u8], start: usize, out: &mut Vec<u8>, dict: &mut Dict) {
+    if block.len() - start < BLOCK_MARGIN {  // Too short to bother with copies.
+        return emit_literal(out, &block[start..]);
     }
     let imax = block.len() - BLOCK_MARGIN;
-    let mut i = 0;
-    let mut literal_start = 0;
+    let mut i = start;
+    let mut literal_start = start;
     'outer: while i < imax {
         let mut copy_offset;
         let mut copy_len;
@@ -254,7 +434,7 @@ fn compress_block<W: Write>(block: &[u8], out: &mut W, dict: &mut Dict)
             match dict.find_best_match_or_add(block, i) {
                 None => {},
                 Some((pos, len)) => {
-                    copy_offset = i as u16 - pos;
+                    copy_offset = i as u32 - pos;
                     copy_len = len;
                     break;
                 }
@@ -263,17 +443,20 @@ fn compress_block<W: Write>(block: &[u8], out: &mut W, dict: &mut Dict)
             if i >= imax { break 'outer; }
         }
 
-        try!(emit_literal(out, &block[literal_start..i]));
+        // Skip the empty literal of a match right at the start of the block, into earlier data.
+        if literal_start < i {
+            emit_literal(out, &block[literal_start..i]);
+        }
 
         loop {
             i += copy_len as usize;
-            try!(emit_copy(out, copy_offset, copy_len));
+            emit_copy(out, copy_offset, copy_len);
             literal_start = i;
             if i >= imax { break 'outer; }
             match dict.find_best_match_or_add(block, i) {
                 None => break,
                 Some((pos, len)) => {
-                    copy_offset = i as u16 - pos;
+                    copy_offset = i as u32 - pos;
                     copy_len = len;
                 }
             }
@@ -281,31 +464,28 @@ fn compress_block<W: Write>(block: &[u8], out: &mut W, dict: &mut Dict)
         i += 1;
     }
     if literal_start < block.len() {
-        emit_literal(out, &block[literal_start..])
-    } else {
-        Ok(())
+        emit_literal(out, &block[literal_start..]);
     }
 }
 
 /// Emits a copy of any size, possibly emitting multiple copy tags.
-fn emit_copy<W: Write>(out: &mut W, offset: u16, len: u16) -> io::Result<()> {
+fn emit_copy(out: &mut Vec<u8>, offset: u32, len: u16) {
     debug_assert!(len >= MIN_COPY_LEN);
-    //writeln!(io::stderr(), "<copy len={} offset={}>", len, offset);
     let mut remaining = len;
     while remaining >= MAX_COPY_LEN + MIN_COPY_LEN {
-        try!(do_emit_copy(out, offset, MAX_COPY_LEN));
+        do_emit_copy(out, offset, MAX_COPY_LEN);
         remaining -= MAX_COPY_LEN;
     }
     if remaining > MAX_COPY_LEN {
         let to_emit = MAX_COPY_LEN - MIN_COPY_LEN;
-        try!(do_emit_copy(out, offset, to_emit));
+        do_emit_copy(out, offset, to_emit);
         remaining -= to_emit;
     }
     // We've made sure not to emit the last MIN_COPY_LEN, so we don't need a check here.
     do_emit_copy(out, offset, remaining)
 }
 
-fn do_emit_copy<W: Write>(out: &mut W, offset: u16, len: u16) -> io::Result<()> {
+fn do_emit_copy(out: &mut Vec<u8>, offset: u32, len: u16) {
     debug_assert!(len >= MIN_COPY_LEN);
     debug_assert!(len <= MAX_COPY_LEN);
     let len = len as u8;
@@ -313,24 +493,27 @@ fn do_emit_copy<W: Write>(out: &mut W, offset: u16, len: u16) -> io::Result<()>
         let n = len - 4;
         let tag = (n << 2) | COPY_1_BYTE | ((offset >> 3) & 0xE0) as u8;
         let low_len = (offset & 0xFF) as u8;
-        try!(out.write(&[tag, low_len]));
-    } else {
+        out.extend_from_slice(&[tag, low_len]);
+    } else if offset <= MAX_COPY_2_BYTE_OFFSET {
         let n = len - 1;
         let tag = (n << 2) | COPY_2_BYTE;
-        try!(out.write(&[tag]));
-        try!(write_u16_le(out, offset as u16));
+        out.push(tag);
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+    } else {
+        let n = len - 1;
+        let tag = (n << 2) | COPY_4_BYTE;
+        out.push(tag);
+        out.extend_from_slice(&offset.to_le_bytes());
     }
-    Ok(())
 }
 
-fn emit_literal<W: Write>(out: &mut W, literal: &[u8]) -> io::Result<()> {
+fn emit_literal(out: &mut Vec<u8>, literal: &[u8]) {
     debug_assert!(literal.len() < ::std::u32::MAX as usize);
-    //writeln!(io::stderr(), "<literal len={}>", literal.len());
     let len = literal.len() - 1;
     if len < 60 {
         let tag = ((len as u8) << 2) | LITERAL;
-        try!(out.write(&[tag]));
-        try!(out.write_all(literal));
+        out.push(tag);
+        out.extend_from_slice(literal);
     } else {
         let mut ds = [0, 0, 0, 0];
         let mut n = (len as u32).to_le();
@@ -342,16 +525,16 @@ fn emit_literal<W: Write>(out: &mut W, literal: &[u8]) -> io::Result<()> {
         }
         let tag = (((59 + count) as u8) << 2) | LITERAL;
         ds[0] = tag;
-        try!(out.write(&ds[..count + 1]));
-        try!(out.write_all(literal));
+        out.extend_from_slice(&ds[..count + 1]);
+        out.extend_from_slice(literal);
     }
-    Ok(())
 }
 
 /// Find the length of the common prefix of slices in block starting at a and b.
-fn common_prefix_length(block: &[u8], a: u16, b: u16) -> u16 {
-    let s1 = block[a as usize..].as_ptr();
-    let s2 = block[b as usize..].as_ptr();
+#[cfg(not(feature = "safe"))]
+fn common_prefix_length(block: &[u8], a: usize, b: usize) -> u16 {
+    let s1 = block[a..].as_ptr();
+    let s2 = block[b..].as_ptr();
     unsafe {
         // TODO The cast to isize "can" (not really due to block size limitations) overflow
         let s2_limit = block.as_ptr().offset(block.len() as isize);
@@ -359,76 +542,21 @@ fn common_prefix_length(block: &[u8], a: u16, b: u16) -> u16 {
     }
 }
 
-// Largely borrowed from the reference Snappy implementation
-// Does not read *s2_limit or beyond.
-// Does not read *(s1 + (s2_limit - s2)) or beyond.
-// Requires that s2_limit >= s2.
-#[cfg(all(target_pointer_width = "64", target_endian = "little"))]
-unsafe fn find_match_length(s1: *const u8, mut s2: *const u8, s2_limit: *const u8) -> u32 {
-    unsafe fn load64(p: *const u8) -> u64 { ptr::read(p as *const u64) }
-
-    let mut matched = 0;
-    while s2 <= s2_limit.offset(-8) {
-        // We have more than 8 bytes available
-        if load64(s2) == load64(s1.offset(matched as isize)) {
-            // They all matched
-            s2 = s2.offset(8);
-            matched += 8;
-        } else {
-            // They didn't so the end of the matching prefix must be somewhere among them.
-            let x = load64(s2) ^ load64(s1.offset(matched as isize));
-            // First non-zero bit is first non-matching bit
-            let matching_bits = x.trailing_zeros();
-            matched += matching_bits / 8;
-            return matched;
-        }
-    }
-    // Handle remaining bytes
-    while s2 < s2_limit {
-        if *s1.offset(matched as isize) == *s2 {
-            s2 = s2.offset(1);
-            matched += 1;
-        } else {
-            return matched;
-        }
-    }
-    return matched;
+#[cfg(feature = "safe")]
+fn common_prefix_length(block: &[u8], a: usize, b: usize) -> u16 {
+    find_match_length_safe(&block[a..], &block[b..]) as u16
 }
 
-#[cfg(not(all(target_pointer_width = "64", target_endian = "little")))]
-unsafe fn find_match_length(s1: *const u8, mut s2: *const u8, s2_limit: *const u8) -> u32 {
-    unsafe fn load_32(p: *const u8) -> u32 { ptr::read(p as *const u32) }
-
-    let mut matched = 0;
-    while s2 <= s2_limit.offset(-4) && load32(s2) == load32(s1.offset(matched as isize)) {
-        s2 = s2.offset(4);
-        matched += 4;
-    }
-    if cfg!(target_endian = "little") {
-        let x = load32(s2) ^ load32(s1.offset(matched as isize));
-        let matching_bits = x.trailing_zeros();
-        matched += matching_bits / 8;
-    } else {
-        while s2 < s2_limit && *s1.offset(matched as isize) == *s2 {
-            s2 = s2.offset(1);
-            matched += 1;
-        }
-    }
-    return matched;
+pub fn write_varint<W: Write>(out: &mut W, n: u32) -> io::Result<()> {
+    let mut ds = [0; 5];
+    let nbytes = encode_varint(n, &mut ds);
+    out.write_all(&ds[..nbytes])
 }
 
-fn write_u16_le<W: Write>(out: &mut W, n: u16) -> io::Result<()> {
-    let le = n.to_le();
-    let ptr = &le as *const u16 as *const u8;
-    let s = unsafe { ::std::slice::from_raw_parts(ptr, 2) };
-    try!(out.write(s));
-    Ok(())
-}
-
-fn write_varint<W: Write>(out: &mut W, n: u32) -> io::Result<()> {
+/// Encodes `n` into the start of `ds`, and returns how many bytes that took.
+pub fn encode_varint(n: u32, ds: &mut [u8; 5]) -> usize {
     let r = 128;
-    let mut ds = [0, 0, 0, 0, 0];
-    let nbytes = if n < (1 << 7) {
+    if n < (1 << 7) {
         ds[0] = n as u8;
         1
     } else if n < (1 << 14) {
@@ -453,14 +581,15 @@ fn write_varint<W: Write>(out: &mut W, n: u32) -> io::Result<()> {
         ds[3] = ((n >> 21) |r) as u8;
         ds[4] = (n >> 28) as u8;
         5
-    };
-    try!(out.write(&ds[..nbytes]));
-    Ok(())
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{write_varint, emit_literal, emit_copy, common_prefix_length};
+    use std::io::{self, Cursor, Write};
+    use decompress::decompress;
+    use super::{write_varint, emit_literal, emit_copy, common_prefix_length, compress_block, compress_with_options,
+                CompressorOptions, Dict, BLOCK_MARGIN, MAX_WINDOW_SIZE, MIN_COPY_LEN};
 
     #[test]
     fn test_write_varint_short() {
@@ -480,7 +609,7 @@ mod test {
     fn test_emit_literal_small() {
         let mut out = Vec::new();
         let literal = &[1, 2, 3, 4, 5, 6, 7];
-        emit_literal(&mut out, literal).unwrap();
+        emit_literal(&mut out, literal);
         assert_eq!(out[0], 0b000110_00);
         assert_eq!(&out[1..], literal);
     }
@@ -489,7 +618,7 @@ mod test {
     fn test_emit_literal_medium() {
         let mut out = Vec::new();
         let literal: Vec<u8> = (0..100).collect();
-        emit_literal(&mut out, &literal[..]).unwrap();
+        emit_literal(&mut out, &literal[..]);
         assert_eq!(&out[..2], &[0b111100_00, (literal.len() - 1) as u8]);
         assert_eq!(&out[2..], &literal[..]);
     }
@@ -499,7 +628,7 @@ mod test {
     fn test_emit_literal_large() {
         let mut out = Vec::new();
         let literal: Vec<u8> = (0..16_777_218).map(|i| (i % 100) as u8).collect();
-        emit_literal(&mut out, &literal[..]).unwrap();
+        emit_literal(&mut out, &literal[..]);
         assert_eq!(&out[..5], &[0b111111_00, 0x01, 0x00, 0x00, 0x01]);
         assert_eq!(&out[5..], &literal[..]);
     }
@@ -507,10 +636,120 @@ mod test {
     #[test]
     fn test_emit_copy_large() {
         let mut out = Vec::new();
-        emit_copy(&mut out, 60_000, 40).unwrap();
+        emit_copy(&mut out, 60_000, 40);
         assert_eq!(&out[..], &[0b100111_10, 0x60, 0xEA]);
     }
 
+    #[test]
+    fn test_emit_copy_far() {
+        let mut out = Vec::new();
+        emit_copy(&mut out, 0x012345, 40);
+        assert_eq!(&out[..], &[0b100111_11, 0x45, 0x23, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn test_emit_copy_far_boundaries() {
+        let mut out = Vec::new();
+        emit_copy(&mut out, 0xFFFF, 8);
+        emit_copy(&mut out, 0x10000, 8);
+        emit_copy(&mut out, 0xFFFF_FFFF, 8);
+        assert_eq!(&out[..], &[0b000111_10, 0xFF, 0xFF,
+                               0b000111_11, 0x00, 0x00, 0x01, 0x00,
+                               0b000111_11, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_emit_copy_far_long() {
+        let mut out = Vec::new();
+        emit_copy(&mut out, 0x10000, 100);
+        assert_eq!(&out[..], &[0b111111_11, 0x00, 0x00, 0x01, 0x00,
+                               0b100011_11, 0x00, 0x00, 0x01, 0x00]);
+    }
+
+    fn compress_window(input: &[u8], window_size: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        let options = CompressorOptions { window_size: window_size, ..Default::default() };
+        compress_with_options(&mut Cursor::new(input), &mut out, &options).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_window() {
+        // 100000 distinct bytes, repeated: only copies further back than one block can find the repeat.
+        let part: Vec<u8> = (0..100_000u32).map(|i| (i * 7919 ^ i >> 5) as u8).collect();
+        let mut input = part.clone();
+        input.extend_from_slice(&part[..]);
+        let plain = compress_window(&input[..], 0);
+        for &window_size in [1 << 16, 1 << 17, MAX_WINDOW_SIZE].iter() {
+            let compressed = compress_window(&input[..], window_size);
+            let mut out = Vec::new();
+            decompress(&mut Cursor::new(&compressed[..]), &mut out).unwrap();
+            assert!(out == input, "window size {}", window_size);
+            if window_size > 100_000 {
+                assert!(compressed.len() < plain.len() * 2 / 3, "window size {}: {} bytes, {} without window",
+                        window_size, compressed.len(), plain.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_window_too_large() {
+        let mut out = Vec::new();
+        let options = CompressorOptions { window_size: MAX_WINDOW_SIZE + 1, ..Default::default() };
+        assert!(compress_with_options(&mut Cursor::new(&b"data"[..]), &mut out, &options).is_err());
+    }
+
+    #[test]
+    fn test_compress_block_shorter_than_margin() {
+        // Options round block sizes up to BLOCK_MARGIN, but the last block of the input, or a
+        // block after history, may still be shorter. Those are one literal, even if they repeat.
+        let history: Vec<u8> = (0..100).collect();
+        for len in 1..BLOCK_MARGIN {
+            for &start in [1, 20, 100].iter() {
+                let mut block = history[..start].to_vec();
+                block.extend(history.iter().cycle().take(len));
+                let mut dict = Dict::new(block.len() as u32);
+                for pos in 0..start.saturating_sub(MIN_COPY_LEN as usize - 1) {
+                    dict.add(&block[..], pos);
+                }
+                let mut out = Vec::new();
+                compress_block(&block[..], start, &mut out, &mut dict);
+                let mut expected = Vec::new();
+                emit_literal(&mut expected, &block[start..]);
+                assert!(out == expected, "len {}, start {}", len, start);
+            }
+        }
+    }
+
+    /// Takes at most 3 bytes per write, like a writer with a full pipe might.
+    struct ShortWriter(Vec<u8>);
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn test_short_writes() {
+        let text = include_bytes!("../tests/moonstone-short.txt");
+        for &window_size in [0, 1 << 20].iter() {
+            let options = CompressorOptions { block_size: 4096, window_size: window_size };
+            let mut expected = Vec::new();
+            compress_with_options(&mut Cursor::new(&text[..]), &mut expected, &options).unwrap();
+            let mut out = ShortWriter(Vec::new());
+            compress_with_options(&mut Cursor::new(&text[..]), &mut out, &options).unwrap();
+            assert!(out.0 == expected);
+            let mut decompressed = Vec::new();
+            decompress(&mut Cursor::new(&out.0[..]), &mut decompressed).unwrap();
+            assert!(&decompressed[..] == &text[..]);
+        }
+    }
+
     #[test]
     fn test_common_prefix_length() {
         assert_eq!(common_prefix_length(&[1, 2, 3, 4, 5, 3, 4, 5], 2, 5), 3);
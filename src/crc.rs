@@ -0,0 +1,33 @@
+include!(concat!(env!("OUT_DIR"), "/crc_tables.rs"));
+
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc = CRC32C_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// The checksum stored in framing format chunks.
+/// Masked so that checksums of data containing checksums are still good.
+pub fn masked_crc32c(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    ((crc >> 15) | (crc << 17)).wrapping_add(0xa282ead8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crc32c, masked_crc32c};
+
+    #[test]
+    fn test_crc32c() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xE3069283);
+        assert_eq!(crc32c(&[0; 32]), 0x8A9136AA);
+    }
+
+    #[test]
+    fn test_masked_crc32c() {
+        assert_eq!(masked_crc32c(b"123456789"), 0xE3069283u32.rotate_right(15).wrapping_add(0xa282ead8));
+    }
+}
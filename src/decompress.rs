@@ -1,15 +1,21 @@
-use std::io::{Write, BufRead};
+use std::io::{Write, BufRead, Cursor};
 use std::io;
+#[cfg(not(feature = "safe"))]
 use std::ptr;
+#[cfg(not(feature = "safe"))]
+use std::slice;
 use std::cmp;
-use std::u16;
-use std::u32;
 use std::result::Result;
 use self::SnappyError::*;
 
 include!(concat!(env!("OUT_DIR"), "/tables.rs"));
 
-const MAX_TAG_LEN: usize = 5;
+pub const MAX_TAG_LEN: usize = 5;
+
+/// The most that `set_uncompressed_length` reserves up front. The length is read from the input,
+/// so a few bytes claiming 4 GiB of output must not allocate 4 GiB. Beyond this, output grows as
+/// it is written.
+pub const MAX_RESERVE: usize = 1 << 20;
 
 pub trait SnappyWrite : Write {
     fn write_from_self(&mut self, offset: u32, len: u8) -> io::Result<()>;
@@ -19,9 +25,28 @@ pub trait SnappyWrite : Write {
 #[derive(Debug)]
 pub enum SnappyError {
     FormatError(&'static str),
-    IoError(io::Error)
+    IoError(io::Error),
+    /// The `CompressorOptions` are out of range.
+    InvalidOptions(&'static str),
+}
+
+impl From<io::Error> for SnappyError {
+    fn from(e: io::Error) -> SnappyError {
+        IoError(e)
+    }
+}
+
+impl From<SnappyError> for io::Error {
+    fn from(e: SnappyError) -> io::Error {
+        match e {
+            FormatError(msg)    => io::Error::new(io::ErrorKind::InvalidData, msg),
+            IoError(e)          => e,
+            InvalidOptions(msg) => io::Error::new(io::ErrorKind::InvalidInput, msg),
+        }
+    }
 }
 
+#[cfg(not(feature = "safe"))]
 struct Decompressor<R> {
     reader: R,
     tmp: [u8; MAX_TAG_LEN],
@@ -30,16 +55,18 @@ struct Decompressor<R> {
     read: usize,
 }
 
+#[cfg(not(feature = "safe"))]
 macro_rules! try_advance_tag {
     ($me: expr) => (
         match $me.advance_tag() {
-            Ok(None)            => return Ok(()),
+            Ok(None)            => break,
             Ok(Some(tag_size))  => tag_size,
             Err(e)              => return Err(e)
         }
     )
 }
 
+#[cfg(not(feature = "safe"))]
 macro_rules! read_new_buffer {
     ($me: expr) => (
         read_new_buffer!($me, return Ok(None))
@@ -57,6 +84,7 @@ macro_rules! read_new_buffer {
     );
 }
 
+#[cfg(not(feature = "safe"))]
 impl <R: BufRead> Decompressor<R> {
     fn new(reader: R) -> Decompressor<R> {
         Decompressor {
@@ -72,7 +100,7 @@ impl <R: BufRead> Decompressor<R> {
         unsafe {
             let buf;
             let buf_end;
-            let mut buf_len;
+            let buf_len;
             if self.available() == 0 {
                 self.reader.consume(self.read);
                 let (b, be) = read_new_buffer!(self);
@@ -88,17 +116,19 @@ impl <R: BufRead> Decompressor<R> {
             let c = ptr::read(buf);
             let tag_size = get_tag_size(c);
             if buf_len < tag_size {
-                ptr::copy(buf, self.tmp.as_mut_ptr(), buf_len);
+                let mut have = 0;
+                stitch_tag(&mut self.tmp, &mut have, slice::from_raw_parts(buf, buf_len));
                 self.reader.consume(self.read);
                 self.read = 0;
-                while buf_len < tag_size {
+                loop {
                     let (newbuf, newbuf_end) = read_new_buffer!(self,
                             return Err(FormatError("EOF while reading tag")));
-                    let newbuf_len = newbuf_end as usize - newbuf as usize;
-                    let to_read = cmp::min(tag_size - buf_len, newbuf_len);  // How many bytes should we read from the new buffer?
-                    ptr::copy_nonoverlapping(newbuf, self.tmp.as_mut_ptr().offset(buf_len as isize), to_read);
-                    buf_len += to_read;
-                    self.reader.consume(to_read);
+                    let newbuf = slice::from_raw_parts(newbuf, newbuf_end as usize - newbuf as usize);
+                    let (used, done) = stitch_tag(&mut self.tmp, &mut have, newbuf);
+                    self.reader.consume(used);
+                    if done.is_some() {
+                        break;
+                    }
                 }
                 self.buf = self.tmp.as_ptr();
                 self.buf_end = self.buf.offset(tag_size as isize);
@@ -116,17 +146,22 @@ impl <R: BufRead> Decompressor<R> {
         }
     }
 
-    fn decompress<W: SnappyWrite>(&mut self, writer: &mut W) -> Result<(), SnappyError> {
+    fn decompress<W: SnappyWrite>(&mut self, writer: &mut W, uncompressed_length: u32) -> Result<(), SnappyError> {
+        let mut remaining_output = uncompressed_length as u64;
         loop {
             let tag_size = try_advance_tag!(self);
             let c = self.read(1)[0];
             if c & 0x03 == 0 {  // literal
                 let literal_len = if tag_size == 1 {
-                    ((c >> 2) as u32) + 1
+                    ((c >> 2) as u64) + 1
                 } else {
                     let literal_len_bytes = (tag_size - 1) as u8;
-                    self.read_u32_le(literal_len_bytes) + 1
+                    self.read_u32_le(literal_len_bytes) as u64 + 1
                 };
+                if literal_len > remaining_output {
+                    return Err(FormatError("output exceeds uncompressed length"));
+                }
+                remaining_output -= literal_len;
                 let mut remaining = literal_len as usize;
                 while self.available() < remaining {
                     let available = self.available();
@@ -169,12 +204,20 @@ impl <R: BufRead> Decompressor<R> {
                 if copy_offset == 0 {  // zero-length copies can't be encoded, no need to check for them
                     return Err(FormatError("zero-length offset"));
                 }
+                if copy_len as u64 > remaining_output {
+                    return Err(FormatError("output exceeds uncompressed length"));
+                }
+                remaining_output -= copy_len as u64;
                 match writer.write_from_self(copy_offset, copy_len) {
                     Ok(_)  => {},
                     Err(e) => return Err(IoError(e))
                 }
             }
         }
+        if remaining_output != 0 {
+            return Err(FormatError("output shorter than uncompressed length"));
+        }
+        Ok(())
     }
 
     fn read(&mut self, n: usize) -> &[u8] {
@@ -201,22 +244,164 @@ impl <R: BufRead> Decompressor<R> {
         const MASKS: &'static [u32] = &[0, 0x000000FF, 0x0000FFFF, 0x00FFFFFF, 0xFFFFFFFF];
         let p = self.buf as *const u32;
         self.advance(bytes as usize);
-        u32::from_le(unsafe { ptr::read(p) }) & MASKS[bytes as usize]
+        u32::from_le(unsafe { ptr::read_unaligned(p) }) & MASKS[bytes as usize]
     }
 
     fn read_u16_le(&mut self) -> u16 {
         let p = self.read(2).as_ptr() as *const u16;
-        let x = unsafe { ptr::read(p) };
+        let x = unsafe { ptr::read_unaligned(p) };
         return u16::from_le(x);
     }
 }
 
+/// The safe counterpart of `Decompressor`, used instead of it when the `safe` feature is enabled.
+/// Tags are copied out of the reader's buffer rather than read in place.
+#[cfg_attr(not(feature = "safe"), allow(dead_code))]
+struct SafeDecompressor<R> {
+    reader: R,
+}
+
+#[cfg_attr(not(feature = "safe"), allow(dead_code))]
+impl <R: BufRead> SafeDecompressor<R> {
+    fn new(reader: R) -> SafeDecompressor<R> {
+        SafeDecompressor {
+            reader: reader,
+        }
+    }
+
+    /// Reads the next tag into `tag` and returns its size, or `None` at EOF.
+    fn read_tag(&mut self, tag: &mut [u8; MAX_TAG_LEN]) -> Result<Option<usize>, SnappyError> {
+        let mut have = 0;
+        loop {
+            let (used, tag_size) = {
+                let buf = try!(self.reader.fill_buf());
+                if buf.len() == 0 {
+                    if have == 0 {
+                        return Ok(None);
+                    }
+                    return Err(FormatError("EOF while reading tag"));
+                }
+                stitch_tag(tag, &mut have, buf)
+            };
+            self.reader.consume(used);
+            if tag_size.is_some() {
+                return Ok(tag_size);
+            }
+        }
+    }
+
+    fn decompress<W: SnappyWrite>(&mut self, writer: &mut W, uncompressed_length: u32) -> Result<(), SnappyError> {
+        let mut remaining_output = uncompressed_length as u64;
+        let mut tag = [0; MAX_TAG_LEN];
+        while let Some(tag_size) = try!(self.read_tag(&mut tag)) {
+            match decode_tag(&tag[..tag_size]) {
+                (literal_len, None) => {
+                    if literal_len > remaining_output {
+                        return Err(FormatError("output exceeds uncompressed length"));
+                    }
+                    remaining_output -= literal_len;
+                    let mut remaining = literal_len as usize;
+                    while remaining > 0 {
+                        let n = {
+                            let buf = try!(self.reader.fill_buf());
+                            if buf.len() == 0 {
+                                return Err(FormatError("EOF while reading literal"));
+                            }
+                            let n = cmp::min(remaining, buf.len());
+                            try!(writer.write_all(&buf[..n]));
+                            n
+                        };
+                        self.reader.consume(n);
+                        remaining -= n;
+                    }
+                },
+                (copy_len, Some(copy_offset)) => {
+                    if copy_offset == 0 {
+                        return Err(FormatError("zero-length offset"));
+                    }
+                    if copy_len > remaining_output {
+                        return Err(FormatError("output exceeds uncompressed length"));
+                    }
+                    remaining_output -= copy_len;
+                    try!(writer.write_from_self(copy_offset, copy_len as u8));
+                }
+            }
+        }
+        if remaining_output != 0 {
+            return Err(FormatError("output shorter than uncompressed length"));
+        }
+        Ok(())
+    }
+}
+
+/// Reads a little endian integer of up to 4 bytes.
+pub fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |n, &b| (n << 8) | b as u32)
+}
+
+/// Collects a tag that may be split across buffers into `tag`, which holds the first `*have`
+/// bytes of it so far. Returns how many bytes of `input` were used, and the size of the tag
+/// once it is complete, when `*have` is reset for the next tag.
+pub fn stitch_tag(tag: &mut [u8; MAX_TAG_LEN], have: &mut usize, input: &[u8]) -> (usize, Option<usize>) {
+    let mut used = 0;
+    if *have == 0 {
+        if input.is_empty() {
+            return (0, None);
+        }
+        tag[0] = input[0];
+        *have = 1;
+        used = 1;
+    }
+    let tag_size = get_tag_size(tag[0]);
+    let n = cmp::min(tag_size - *have, input.len() - used);
+    tag[*have..*have + n].copy_from_slice(&input[used..used + n]);
+    *have += n;
+    used += n;
+    if *have < tag_size {
+        return (used, None);
+    }
+    *have = 0;
+    (used, Some(tag_size))
+}
+
+/// Decodes a complete tag into the length of its output and, for copies, the offset.
+pub fn decode_tag(tag: &[u8]) -> (u64, Option<u32>) {
+    let c = tag[0];
+    if c & 0x03 == 0 {
+        let len = if tag.len() == 1 {
+            (c >> 2) as u64 + 1
+        } else {
+            read_u32_le(&tag[1..]) as u64 + 1
+        };
+        (len, None)
+    } else if tag.len() == 2 {
+        (4 + ((c & 0x1C) >> 2) as u64, Some((((c & 0xE0) as u32) << 3) | tag[1] as u32))
+    } else {
+        (1 + (c >> 2) as u64, Some(read_u32_le(&tag[1..])))
+    }
+}
+
 #[inline(never)]
 pub fn decompress<R: BufRead, W: SnappyWrite>(reader: &mut R, writer: &mut W) -> Result<(), SnappyError> {
     let uncompressed_length = try!(read_uncompressed_length(reader));
     writer.set_uncompressed_length(uncompressed_length);
+    #[cfg(not(feature = "safe"))]
     let mut decompressor = Decompressor::new(reader);
-    decompressor.decompress(writer)
+    #[cfg(feature = "safe")]
+    let mut decompressor = SafeDecompressor::new(reader);
+    decompressor.decompress(writer, uncompressed_length)
+}
+
+/// Returns the uncompressed length at the start of `input`, without decompressing it.
+pub fn peek_uncompressed_length(input: &[u8]) -> Result<u32, SnappyError> {
+    read_uncompressed_length(&mut Cursor::new(input))
+}
+
+/// Returns the most that `compressed_len` bytes can decompress to.
+///
+/// No tag makes more than 22 times its own size of output: a 3 byte copy tag copies at most 64 bytes.
+pub fn max_uncompressed_length(compressed_len: usize) -> usize {
+    compressed_len.saturating_mul(22)
 }
 
 fn read_uncompressed_length<R: BufRead>(reader: &mut R) -> Result<u32, SnappyError> {
@@ -249,19 +434,49 @@ fn read_uncompressed_length<R: BufRead>(reader: &mut R) -> Result<u32, SnappyErr
     }
 }
 
+pub fn check_copy_offset(offset: u32, written: usize) -> io::Result<()> {
+    if offset == 0 || offset as usize > written {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "copy offset before start of output"));
+    }
+    Ok(())
+}
+
+/// The safe counterpart of `write_from_self` for `Vec`.
+#[cfg_attr(not(feature = "safe"), allow(dead_code))]
+fn vec_write_from_self_safe(v: &mut Vec<u8>, offset: u32, len: u8) -> io::Result<()> {
+    try!(check_copy_offset(offset, v.len()));
+    let start = v.len() - offset as usize;
+    if len as u32 <= offset {
+        v.extend_from_within(start..start + len as usize);
+    } else {
+        // The copy overlaps itself, so it must be done one byte at a time.
+        for i in 0..len as usize {
+            let c = v[start + i];
+            v.push(c);
+        }
+    }
+    Ok(())
+}
+
 impl SnappyWrite for Vec<u8> {
+    #[cfg(feature = "safe")]
+    fn write_from_self(&mut self, offset: u32, len: u8) -> io::Result<()> {
+        vec_write_from_self_safe(self, offset, len)
+    }
+
+    #[cfg(not(feature = "safe"))]
     #[inline]
     fn write_from_self(&mut self, offset: u32, len: u8) -> io::Result<()> {
+        try!(check_copy_offset(offset, self.len()));
         let start = self.len() - offset as usize;
         let space_left = self.capacity() - self.len();
         if len <= 16 && offset >= 8 && space_left >= 16 {
             // Fast path
-            assert!((offset as usize) <= self.len());
             unsafe {
                 let src = self.as_ptr().offset(start as isize) as *const u64;
                 let dst = self.as_mut_ptr().offset(self.len() as isize) as *mut u64;
-                ptr::write(dst, ptr::read(src));
-                ptr::write(dst.offset(1), ptr::read(src.offset(1)));
+                ptr::write_unaligned(dst, ptr::read_unaligned(src));
+                ptr::write_unaligned(dst.offset(1), ptr::read_unaligned(src.offset(1)));
                 let new_len = self.len() + len as usize;
                 self.set_len(new_len);
             }
@@ -276,16 +491,54 @@ impl SnappyWrite for Vec<u8> {
     }
 
     fn set_uncompressed_length(&mut self, length: u32) {
-        self.reserve_exact(length as usize);
+        self.reserve_exact(cmp::min(length as usize, MAX_RESERVE));
+    }
+}
+
+/// Decompresses into a preallocated slice, starting at the position of the cursor.
+///
+/// Output that does not fit in the slice fails with `ErrorKind::WriteZero`. A plain `&mut [u8]`
+/// can not be a `SnappyWrite`, since writing to it drops the output that copies refer back to.
+impl SnappyWrite for Cursor<&mut [u8]> {
+    fn write_from_self(&mut self, offset: u32, len: u8) -> io::Result<()> {
+        let pos = self.position() as usize;
+        try!(check_copy_offset(offset, pos));
+        let end = pos + len as usize;
+        if end > self.get_ref().len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "output does not fit in buffer"));
+        }
+        let start = pos - offset as usize;
+        {
+            let buf = self.get_mut();
+            if len as u32 <= offset {
+                buf.copy_within(start..start + len as usize, pos);
+            } else {
+                for i in 0..len as usize {
+                    buf[pos + i] = buf[start + i];
+                }
+            }
+        }
+        self.set_position(end as u64);
+        Ok(())
+    }
+
+    fn set_uncompressed_length(&mut self, _length: u32) {
     }
 }
 
 
 #[cfg(test)]
 mod test {
-    use std::io::Cursor;
+    use std::io::{Cursor, ErrorKind};
     // TODO rustc warns about unused import, but can not compile with out it
-    use super::{read_uncompressed_length, SnappyWrite};
+    use super::{decode_tag, decompress, read_uncompressed_length, stitch_tag, vec_write_from_self_safe, SnappyWrite,
+                MAX_RESERVE, MAX_TAG_LEN};
+    #[cfg(not(feature = "safe"))]
+    use super::{Decompressor, SafeDecompressor, SnappyError};
+    #[cfg(not(feature = "safe"))]
+    use std::io::BufReader;
+    #[cfg(not(feature = "safe"))]
+    use compress::{compress_with_options, CompressorOptions};
 
     #[test]
     fn test_read_uncompressed_length_long() {
@@ -299,6 +552,33 @@ mod test {
         assert_eq!(read_uncompressed_length(&mut Cursor::new(&inp[..])).unwrap(), 64);
     }
 
+    #[test]
+    fn test_stitch_tag() {
+        // A 4 byte offset copy, one byte at a time, then a literal tag in the same input
+        let input = [0b000111_11, 0x45, 0x23, 0x01, 0x00, 0b000010_00];
+        let mut tag = [0; MAX_TAG_LEN];
+        let mut have = 0;
+        assert_eq!(stitch_tag(&mut tag, &mut have, &[]), (0, None));
+        for i in 0..4 {
+            assert_eq!(stitch_tag(&mut tag, &mut have, &input[i..i + 1]), (1, None));
+            assert_eq!(have, i + 1);
+        }
+        assert_eq!(stitch_tag(&mut tag, &mut have, &input[4..]), (1, Some(5)));
+        assert_eq!(have, 0);
+        assert_eq!(decode_tag(&tag[..5]), (8, Some(0x012345)));
+        assert_eq!(stitch_tag(&mut tag, &mut have, &input[5..]), (1, Some(1)));
+        assert_eq!(decode_tag(&tag[..1]), (3, None));
+    }
+
+    #[test]
+    fn test_vec_reserve_capped() {
+        // A few bytes of input may claim 4 GiB of output
+        let mut v = Vec::new();
+        v.set_uncompressed_length(::std::u32::MAX);
+        assert!(v.capacity() <= MAX_RESERVE);
+        assert!(decompress(&mut Cursor::new(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0][..]), &mut v).is_err());
+    }
+
     #[test]
     fn test_vec_write_from_self() {
         let mut xs = vec![1, 2, 3, 4];
@@ -313,6 +593,13 @@ mod test {
         assert_eq!(&xs[..], &[1, 2, 3, 2, 3, 2, 3]);
     }
 
+    #[test]
+    fn test_vec_write_from_self_offset_too_large() {
+        let mut xs = vec![1, 2, 3];
+        assert!(xs.write_from_self(4, 2).is_err());
+        assert_eq!(&xs[..], &[1, 2, 3]);
+    }
+
     #[test]
     fn test_vec_write_from_self_fast_path() {
         let mut xs = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
@@ -336,4 +623,124 @@ mod test {
         assert_eq!(xs.len(), n + len as usize);
         assert_eq!(&xs[start..start + len as usize], &xs[xs.len() - len as usize..]);
     }
+
+    #[test]
+    fn test_vec_write_from_self_zero_offset() {
+        let mut xs = vec![1, 2, 3];
+        assert!(xs.write_from_self(0, 2).is_err());
+        assert!(vec_write_from_self_safe(&mut xs, 0, 2).is_err());
+    }
+
+    #[test]
+    fn test_vec_write_from_self_safe() {
+        for &(offset, len) in [(1, 1), (1, 9), (3, 2), (2, 4), (8, 16), (9, 4), (20, 20), (40, 64)].iter() {
+            let mut xs: Vec<u8> = (0..40).collect();
+            let mut ys = xs.clone();
+            xs.write_from_self(offset, len).unwrap();
+            vec_write_from_self_safe(&mut ys, offset, len).unwrap();
+            assert_eq!(xs, ys, "offset={} len={}", offset, len);
+        }
+        let mut xs = vec![1, 2, 3];
+        assert!(vec_write_from_self_safe(&mut xs, 4, 2).is_err());
+    }
+
+    /// Decompresses `input` with both decompressors, reading through a buffer of `capacity` bytes
+    /// so that tags and literals are split across reads.
+    #[cfg(not(feature = "safe"))]
+    fn decompress_both(input: &[u8], capacity: usize) -> (Result<Vec<u8>, SnappyError>, Result<Vec<u8>, SnappyError>) {
+        let mut reader = BufReader::with_capacity(capacity, input);
+        let fast = read_uncompressed_length(&mut reader).and_then(|len| {
+            let mut out = Vec::with_capacity(len as usize);
+            Decompressor::new(&mut reader).decompress(&mut out, len).map(|_| out)
+        });
+        let mut reader = BufReader::with_capacity(capacity, input);
+        let safe = read_uncompressed_length(&mut reader).and_then(|len| {
+            let mut out = Vec::with_capacity(len as usize);
+            SafeDecompressor::new(&mut reader).decompress(&mut out, len).map(|_| out)
+        });
+        (fast, safe)
+    }
+
+    #[cfg(not(feature = "safe"))]
+    #[test]
+    fn test_safe_decompressor_same_as_fast() {
+        let text = include_bytes!("../tests/moonstone-short.txt");
+        let mut inputs = Vec::new();
+        for &block_size in [16, 1000, 65535].iter() {
+            let mut compressed = Vec::new();
+            let options = CompressorOptions { block_size: block_size, ..Default::default() };
+            compress_with_options(&mut Cursor::new(&text[..20000]), &mut compressed, &options).unwrap();
+            inputs.push(compressed);
+        }
+        // Long literals, with 1 to 4 length bytes
+        for &len in [61, 300, 70000].iter() {
+            let mut input = vec![0x80 | (len & 0x7F) as u8, (len >> 7) as u8];
+            if len >= 1 << 14 {
+                input[1] |= 0x80;
+                input.push((len >> 14) as u8);
+            }
+            let n = len - 1;
+            let bytes = if n < 1 << 8 { 1 } else if n < 1 << 16 { 2 } else { 3 };
+            input.push(((59 + bytes) << 2) as u8);
+            for i in 0..bytes {
+                input.push((n >> (8 * i)) as u8);
+            }
+            input.extend((0..len).map(|i| i as u8));
+            inputs.push(input);
+        }
+        // Copies with 2 and 4 byte offsets
+        inputs.push(vec![13, 0x08, 1, 2, 3, 0x26, 3, 0]);
+        inputs.push(vec![13, 0x08, 1, 2, 3, 0x27, 3, 0, 0, 0]);
+        // Malformed: truncated, and with copy offsets out of range
+        let truncated: Vec<Vec<u8>> = inputs.iter().flat_map(|i| {
+            vec![i[..i.len() / 2].to_vec(), i[..i.len() - 1].to_vec()]
+        }).collect();
+        inputs.extend(truncated);
+        inputs.push(vec![5, 0x01, 0x05]);
+        inputs.push(vec![13, 0x08, 1, 2, 3, 0x27, 4, 0, 0, 0]);
+        inputs.push(vec![13, 0x08, 1, 2, 3, 0x27, 0, 0, 0, 0]);
+
+        for input in inputs.iter() {
+            for &capacity in [1, 2, 3, 7, 64, 8192].iter() {
+                match decompress_both(&input[..], capacity) {
+                    (Ok(fast), Ok(safe)) => assert!(fast == safe, "capacity {}, input {:?}", capacity, input),
+                    (Err(_), Err(_))     => {},
+                    (fast, safe)         => panic!("capacity {}: fast {:?}, safe {:?}", capacity, fast.is_ok(), safe.is_ok())
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cursor_write_from_self() {
+        let mut buf = [1, 2, 3, 0, 0, 0, 0, 0, 0, 0];
+        let mut cursor = Cursor::new(&mut buf[..]);
+        cursor.set_position(3);
+        cursor.write_from_self(3, 2).unwrap();
+        cursor.write_from_self(2, 4).unwrap();
+        assert_eq!(cursor.position(), 9);
+        assert!(cursor.write_from_self(10, 1).is_err());
+        assert!(cursor.write_from_self(0, 1).is_err());
+        assert_eq!(cursor.write_from_self(1, 2).unwrap_err().kind(), ErrorKind::WriteZero);
+        cursor.write_from_self(1, 1).unwrap();
+        assert_eq!(buf, [1, 2, 3, 1, 2, 1, 2, 1, 2, 2]);
+    }
+
+    #[test]
+    fn test_decompress_into_slice() {
+        let compressed = [13, 0x08, 1, 2, 3, 0x26, 3, 0];
+        let mut buf = [0; 13];
+        {
+            let mut cursor = Cursor::new(&mut buf[..]);
+            ::decompress(&mut Cursor::new(&compressed[..]), &mut cursor).unwrap();
+            assert_eq!(cursor.position(), 13);
+        }
+        assert_eq!(buf, [1, 2, 3, 1, 2, 3, 1, 2, 3, 1, 2, 3, 1]);
+
+        for &len in [0, 1, 4, 12].iter() {
+            let mut buf = vec![0; len];
+            let err = ::decompress(&mut Cursor::new(&compressed[..]), &mut Cursor::new(&mut buf[..])).unwrap_err();
+            assert_eq!(::std::io::Error::from(err).kind(), ErrorKind::WriteZero, "buffer of {} bytes", len);
+        }
+    }
 }
@@ -0,0 +1,266 @@
+//! Compression with a preset dictionary. This is a non-standard extension of Snappy.
+//!
+//! Both sides are primed with the same dictionary, which copies may refer to as if it preceded
+//! the data. This helps with small messages that share a lot of content, which on their own
+//! are too short to compress well. Copies into the dictionary are only made from the first
+//! block, unless `CompressorOptions::window_size` lets any block reach back that far.
+//!
+//! The output has the same syntax as the raw format, but other Snappy implementations will reject
+//! it (or worse, decode it wrongly if they ignore offsets that reach before the start of the data).
+//! It must only be decompressed with `decompress_with_dictionary` and the same dictionary.
+
+use std::cmp;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::io;
+use compress::{compress_with_history, CompressorOptions, SnappyRead};
+use decompress::{decompress, SnappyError};
+
+/// The largest dictionary `compress_with_dictionary` accepts.
+pub const MAX_DICTIONARY_SIZE: usize = 32 * 1024;
+
+/// Compresses `inp` to the raw format, with copies that may refer to `dictionary`.
+///
+/// The block size is reduced if needed, so that the first block and the dictionary together
+/// fit in `MAX_BLOCK_SIZE`.
+pub fn compress_with_dictionary<R: SnappyRead, W: Write>(inp: &mut R, out: &mut W, dictionary: &[u8],
+                                                         options: &CompressorOptions) -> io::Result<()> {
+    if dictionary.len() > MAX_DICTIONARY_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "dictionary larger than MAX_DICTIONARY_SIZE"));
+    }
+    compress_with_history(inp, out, options, dictionary)
+}
+
+/// Decompresses data made by `compress_with_dictionary` with the same dictionary,
+/// appending the output to `out`.
+pub fn decompress_with_dictionary<R: BufRead>(reader: &mut R, out: &mut Vec<u8>,
+                                              dictionary: &[u8]) -> Result<(), SnappyError> {
+    // Copies may reach into the dictionary but no further, so it goes into a buffer of its own.
+    let mut buf = Vec::with_capacity(dictionary.len());
+    buf.extend_from_slice(dictionary);
+    try!(decompress(reader, &mut buf));
+    if out.is_empty() {
+        buf.drain(..dictionary.len());
+        *out = buf;
+    } else {
+        out.extend_from_slice(&buf[dictionary.len()..]);
+    }
+    Ok(())
+}
+
+/// Length of the substrings counted by `train_dictionary`.
+const TRAIN_KEY_LEN: usize = 8;
+
+/// Builds a dictionary of at most `size` bytes (and at most `MAX_DICTIONARY_SIZE`) from
+/// substrings that recur in several of `samples`.
+///
+/// Each sample should be one message, as it would be compressed on its own. The substrings found
+/// in the most samples are placed at the end of the dictionary, where copies into it are cheapest.
+pub fn train_dictionary(samples: &[&[u8]], size: usize) -> Vec<u8> {
+    let size = cmp::min(size, MAX_DICTIONARY_SIZE);
+
+    // In how many samples each key occurs
+    let mut counts: HashMap<&[u8], u32> = HashMap::new();
+    for sample in samples.iter() {
+        let keys: HashSet<&[u8]> = sample.windows(TRAIN_KEY_LEN).collect();
+        for key in keys {
+            *counts.entry(key).or_insert(0) += 1;
+        }
+    }
+
+    // Candidates are the longest runs of keys that occur in more than one sample.
+    let mut candidates: HashSet<&[u8]> = HashSet::new();
+    for sample in samples.iter() {
+        let mut i = 0;
+        while i + TRAIN_KEY_LEN <= sample.len() {
+            let mut end = i;
+            while end + TRAIN_KEY_LEN <= sample.len() && counts[&sample[end..end + TRAIN_KEY_LEN]] > 1 {
+                end += 1;
+            }
+            if end > i {
+                candidates.insert(&sample[i..end + TRAIN_KEY_LEN - 1]);
+                i = end;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    // Greedily pick the candidate that saves the most bytes over all samples, counting only
+    // keys that are not already in the dictionary. Scores only decrease as the dictionary grows,
+    // so a candidate whose stored score is still current after rescoring is the best one.
+    let mut covered: HashSet<&[u8]> = HashSet::new();
+    let mut heap: BinaryHeap<(u64, Reverse<&[u8]>)> = candidates.into_iter().map(|c| {
+        (score(c, &counts, &covered), Reverse(c))
+    }).collect();
+    let mut segments: Vec<&[u8]> = Vec::new();
+    let mut len = 0;
+    while let Some((stored, Reverse(segment))) = heap.pop() {
+        if len == size {
+            break;
+        }
+        let current = score(segment, &counts, &covered);
+        if current == 0 {
+            continue;
+        }
+        if current < stored {
+            heap.push((current, Reverse(segment)));
+            continue;
+        }
+        // Trim keys that are already covered from both ends
+        let is_new = |w: &[u8]| !covered.contains(w);
+        let first = segment.windows(TRAIN_KEY_LEN).position(&is_new).unwrap();
+        let last = segment.windows(TRAIN_KEY_LEN).rposition(&is_new).unwrap();
+        let segment = &segment[first..cmp::min(last + TRAIN_KEY_LEN, first + size - len)];
+        covered.extend(segment.windows(TRAIN_KEY_LEN));
+        len += segment.len();
+        segments.push(segment);
+    }
+
+    // The best segments go last, closest to the data.
+    let mut dictionary = Vec::with_capacity(len);
+    for segment in segments.iter().rev() {
+        dictionary.extend_from_slice(segment);
+    }
+    dictionary
+}
+
+/// The number of samples containing each key of `segment` that is not yet `covered`, summed.
+fn score(segment: &[u8], counts: &HashMap<&[u8], u32>, covered: &HashSet<&[u8]>) -> u64 {
+    let mut seen = HashSet::new();
+    segment.windows(TRAIN_KEY_LEN)
+           .filter(|w| !covered.contains(w) && seen.insert(*w))
+           .map(|w| counts[w] as u64)
+           .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use compress::{compress, CompressorOptions};
+    use super::{compress_with_dictionary, decompress_with_dictionary, train_dictionary, MAX_DICTIONARY_SIZE};
+
+    static DICTIONARY: &'static [u8] = b"{\"jsonrpc\": \"2.0\", \"method\": \"get_user\", \"params\": {\"id\": ";
+
+    fn compress_dict(input: &[u8], dictionary: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        compress_with_dictionary(&mut Cursor::new(input), &mut out, dictionary, &Default::default()).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_dictionary_roundtrip() {
+        let input = b"{\"jsonrpc\": \"2.0\", \"method\": \"get_user\", \"params\": {\"id\": 12345}, \"id\": 7}";
+        let compressed = compress_dict(input, DICTIONARY);
+        let mut plain = Vec::new();
+        compress(&mut Cursor::new(&input[..]), &mut plain).unwrap();
+        assert!(compressed.len() < plain.len() / 2, "{} bytes, {} without dictionary", compressed.len(), plain.len());
+
+        let mut out = Vec::new();
+        decompress_with_dictionary(&mut Cursor::new(&compressed[..]), &mut out, DICTIONARY).unwrap();
+        assert_eq!(&out[..], &input[..]);
+    }
+
+    #[test]
+    fn test_dictionary_appends() {
+        let input = b"\"method\": \"get_user\", \"params\": {\"id\": 1}";
+        let compressed = compress_dict(input, DICTIONARY);
+        let mut out = b"prefix".to_vec();
+        decompress_with_dictionary(&mut Cursor::new(&compressed[..]), &mut out, DICTIONARY).unwrap();
+        assert_eq!(&out[..6], b"prefix");
+        assert_eq!(&out[6..], &input[..]);
+    }
+
+    #[test]
+    fn test_dictionary_multiple_blocks() {
+        let input: Vec<u8> = DICTIONARY.iter().cycle().take(100_000).cloned().collect();
+        for &block_size in [16, 100, 65535].iter() {
+            let mut compressed = Vec::new();
+            let options = CompressorOptions { block_size: block_size, ..Default::default() };
+            compress_with_dictionary(&mut Cursor::new(&input[..]), &mut compressed, DICTIONARY, &options).unwrap();
+            let mut out = Vec::new();
+            decompress_with_dictionary(&mut Cursor::new(&compressed[..]), &mut out, DICTIONARY).unwrap();
+            assert!(out == input, "block size {}", block_size);
+        }
+    }
+
+    #[test]
+    fn test_dictionary_largest() {
+        let dictionary: Vec<u8> = (0..MAX_DICTIONARY_SIZE).map(|i| (i * 31 % 253) as u8).collect();
+        let input: Vec<u8> = dictionary.iter().rev().cycle().take(200_000).cloned().collect();
+        let compressed = compress_dict(&input[..], &dictionary[..]);
+        let mut out = Vec::new();
+        decompress_with_dictionary(&mut Cursor::new(&compressed[..]), &mut out, &dictionary[..]).unwrap();
+        assert!(out == input);
+
+        let mut too_large = dictionary.clone();
+        too_large.push(0);
+        let mut compressed = Vec::new();
+        assert!(compress_with_dictionary(&mut Cursor::new(&input[..]), &mut compressed, &too_large[..],
+                                         &Default::default()).is_err());
+    }
+
+    #[test]
+    fn test_dictionary_required() {
+        let input = b"\"jsonrpc\": \"2.0\", \"method\": \"get_user\"";
+        let compressed = compress_dict(input, DICTIONARY);
+        let mut out = Vec::new();
+        assert!(::decompress(&mut Cursor::new(&compressed[..]), &mut out).is_err());
+        // A shorter dictionary leaves copies reaching before the start of the data.
+        let mut out = Vec::new();
+        assert!(decompress_with_dictionary(&mut Cursor::new(&compressed[..]), &mut out, &DICTIONARY[30..]).is_err());
+    }
+
+    #[test]
+    fn test_empty_dictionary() {
+        let input = b"no dictionary, no dictionary, no dictionary";
+        let compressed = compress_dict(input, &[]);
+        let mut plain = Vec::new();
+        compress(&mut Cursor::new(&input[..]), &mut plain).unwrap();
+        assert_eq!(compressed, plain);
+    }
+
+    fn messages() -> Vec<Vec<u8>> {
+        (0..200).map(|i| {
+            format!("{{\"jsonrpc\": \"2.0\", \"method\": \"{}\", \"params\": {{\"id\": {}, \"verbose\": {}}}, \"id\": {}}}",
+                    ["get_user", "list_users", "delete_user"][i % 3], i * 7919 % 1000, i % 2 == 0, i).into_bytes()
+        }).collect()
+    }
+
+    #[test]
+    fn test_train_dictionary() {
+        let messages = messages();
+        let samples: Vec<&[u8]> = messages.iter().map(|m| &m[..]).collect();
+        let dictionary = train_dictionary(&samples[..], 1000);
+        assert!(dictionary.len() <= 1000);
+        let contains = |s: &[u8]| dictionary.windows(s.len()).any(|w| w == s);
+        assert!(contains(b"\"jsonrpc\": \"2.0\", \"method\": \""));
+        assert!(contains(b"list_users"));
+
+        let (mut plain, mut with_dictionary) = (0, 0);
+        for message in messages.iter() {
+            let mut out = Vec::new();
+            compress(&mut Cursor::new(&message[..]), &mut out).unwrap();
+            plain += out.len();
+            let compressed = compress_dict(&message[..], &dictionary[..]);
+            with_dictionary += compressed.len();
+            let mut out = Vec::new();
+            decompress_with_dictionary(&mut Cursor::new(&compressed[..]), &mut out, &dictionary[..]).unwrap();
+            assert_eq!(&out[..], &message[..]);
+        }
+        assert!(with_dictionary < plain / 2, "{} bytes, {} without dictionary", with_dictionary, plain);
+    }
+
+    #[test]
+    fn test_train_dictionary_size() {
+        let messages = messages();
+        let samples: Vec<&[u8]> = messages.iter().map(|m| &m[..]).collect();
+        assert_eq!(train_dictionary(&samples[..], 0), Vec::<u8>::new());
+        assert_eq!(train_dictionary(&samples[..], 20).len(), 20);
+        assert_eq!(train_dictionary(&samples[..], 20), train_dictionary(&samples[..], 20));
+        assert!(train_dictionary(&[], 100).is_empty());
+        // Nothing recurs in a single sample
+        assert!(train_dictionary(&samples[..1], 100).is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! The Snappy framing format, as described in `framing_format.txt` of the reference implementation.
+//!
+//! A stream is a sequence of chunks, each a one byte chunk type and a three byte little endian
+//! length followed by the chunk data. It starts with a stream identifier chunk, and data chunks
+//! carry a masked CRC-32C of their uncompressed data.
+
+use std::io::{Read, Write, Cursor};
+use std::io;
+use std::cmp;
+use compress::compress;
+use decompress::{decompress, peek_uncompressed_length, read_u32_le};
+use crc::masked_crc32c;
+use util::{read_exact_or_eof, read_chunk};
+
+/// The stream identifier chunk that every framed stream starts with.
+pub const FRAMING_MAGIC: [u8; 10] = [0xFF, 0x06, 0x00, 0x00, b's', b'N', b'a', b'P', b'p', b'Y'];
+
+const COMPRESSED_DATA: u8 = 0x00;
+const UNCOMPRESSED_DATA: u8 = 0x01;
+const STREAM_IDENTIFIER: u8 = 0xFF;
+
+/// The maximum uncompressed length of the data in one chunk.
+const MAX_FRAME_DATA_SIZE: usize = 65536;
+
+fn format_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u32_le<W: Write>(out: &mut W, n: u32) -> io::Result<()> {
+    out.write_all(&[n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8])
+}
+
+/// Compresses everything written to it into the framing format.
+///
+/// The stream identifier is written on the first write, or when the writer is finished,
+/// so even an empty stream gets one. Data that does not compress is stored uncompressed.
+/// Any partial chunk is written by `flush`, `into_inner` or when the writer is dropped.
+pub struct FramedWriter<W: Write> {
+    inner: Option<W>,
+    wrote_identifier: bool,
+    buf: Vec<u8>,
+    compressed: Vec<u8>,
+}
+
+impl <W: Write> FramedWriter<W> {
+    pub fn new(inner: W) -> FramedWriter<W> {
+        FramedWriter {
+            inner: Some(inner),
+            wrote_identifier: false,
+            buf: Vec::with_capacity(MAX_FRAME_DATA_SIZE),
+            compressed: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Writes any buffered data and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        try!(self.write_chunk());
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn write_identifier(&mut self) -> io::Result<()> {
+        if self.wrote_identifier {
+            return Ok(());
+        }
+        try!(self.inner.as_mut().unwrap().write_all(&FRAMING_MAGIC));
+        self.wrote_identifier = true;
+        Ok(())
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        try!(self.write_identifier());
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.compressed.clear();
+        try!(compress(&mut Cursor::new(&self.buf[..]), &mut self.compressed));
+        let (chunk_type, data) = if self.compressed.len() < self.buf.len() {
+            (COMPRESSED_DATA, &self.compressed[..])
+        } else {
+            (UNCOMPRESSED_DATA, &self.buf[..])
+        };
+        let inner = self.inner.as_mut().unwrap();
+        try!(write_u32_le(inner, chunk_type as u32 | ((data.len() as u32 + 4) << 8)));
+        try!(write_u32_le(inner, masked_crc32c(&self.buf[..])));
+        try!(inner.write_all(data));
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl <W: Write> Write for FramedWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        try!(self.write_identifier());
+        let n = cmp::min(data.len(), MAX_FRAME_DATA_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == MAX_FRAME_DATA_SIZE {
+            try!(self.write_chunk());
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.write_chunk());
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl <W: Write> Drop for FramedWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_chunk();
+        }
+    }
+}
+
+/// Decompresses a framed stream, verifying the checksum of every data chunk.
+pub struct FramedReader<R: Read> {
+    inner: R,
+    read_identifier: bool,
+    block: Vec<u8>,
+    pos: usize,
+    chunk: Vec<u8>,
+}
+
+impl <R: Read> FramedReader<R> {
+    pub fn new(inner: R) -> FramedReader<R> {
+        FramedReader {
+            inner: inner,
+            read_identifier: false,
+            block: Vec::new(),
+            pos: 0,
+            chunk: Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads chunks until one containing data is found. Returns false on EOF.
+    fn read_block(&mut self) -> io::Result<bool> {
+        loop {
+            let mut header = [0; 4];
+            if !try!(read_exact_or_eof(&mut self.inner, &mut header)) {
+                if !self.read_identifier {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "missing stream identifier"));
+                }
+                return Ok(false);
+            }
+            let chunk_type = header[0];
+            let len = read_u32_le(&header) >> 8;
+            try!(read_chunk(&mut self.inner, len, &mut self.chunk));
+            if chunk_type == STREAM_IDENTIFIER {
+                if self.chunk[..] != FRAMING_MAGIC[4..] {
+                    return Err(format_error("invalid stream identifier"));
+                }
+                self.read_identifier = true;
+                continue;
+            }
+            if !self.read_identifier {
+                return Err(format_error("missing stream identifier"));
+            }
+            match chunk_type {
+                COMPRESSED_DATA | UNCOMPRESSED_DATA => {
+                    if self.chunk.len() < 4 {
+                        return Err(format_error("data chunk too short for checksum"));
+                    }
+                    let data = &self.chunk[4..];
+                    let data_len = if chunk_type == COMPRESSED_DATA {
+                        try!(peek_uncompressed_length(data)) as usize
+                    } else {
+                        data.len()
+                    };
+                    // Checked before decompressing, since the length has not been checked yet
+                    if data_len > MAX_FRAME_DATA_SIZE {
+                        return Err(format_error("chunk data exceeds 65536 bytes"));
+                    }
+                    self.block.clear();
+                    self.pos = 0;
+                    if chunk_type == COMPRESSED_DATA {
+                        try!(decompress(&mut Cursor::new(data), &mut self.block));
+                    } else {
+                        self.block.extend_from_slice(data);
+                    }
+                    if masked_crc32c(&self.block[..]) != read_u32_le(&self.chunk[..4]) {
+                        return Err(format_error("checksum mismatch"));
+                    }
+                    return Ok(true);
+                },
+                0x02..=0x7F => return Err(format_error("reserved unskippable chunk")),
+                _ => { }  // Padding (0xFE) and reserved skippable chunks
+            }
+        }
+    }
+}
+
+impl <R: Read> Read for FramedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.block.len() {
+            if !try!(self.read_block()) {
+                return Ok(0);
+            }
+        }
+        let n = cmp::min(buf.len(), self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,151 @@
+//! The block stream format written by Hadoop's `SnappyCodec`.
+//!
+//! A stream is a sequence of blocks. Each block starts with the big endian u32 length of its
+//! uncompressed data, followed by one or more chunks. Each chunk is a big endian u32 length
+//! followed by that many bytes of raw Snappy data, and the chunks of a block together
+//! decompress to the block's uncompressed length.
+
+use std::io::{Read, Write, Cursor};
+use std::io;
+use std::cmp;
+use compress::compress;
+use decompress::{decompress, peek_uncompressed_length};
+use util::{write_u32_be, read_u32_be, read_u32_be_or_eof, read_chunk};
+
+/// The largest block Hadoop will accept with its default 256 KiB buffer size.
+/// Hadoop reserves room for the worst-case compression overhead within the buffer.
+pub const HADOOP_BLOCK_SIZE: usize = 256 * 1024 - (256 * 1024 / 6 + 32);
+
+/// Compresses everything written to it into the Hadoop block stream format.
+///
+/// Data is buffered until a whole block is available. Any partial block is written
+/// by `flush`, `into_inner` or when the writer is dropped.
+pub struct HadoopWriter<W: Write> {
+    inner: Option<W>,
+    block_size: usize,
+    buf: Vec<u8>,
+    compressed: Vec<u8>,
+}
+
+impl <W: Write> HadoopWriter<W> {
+    pub fn new(inner: W) -> HadoopWriter<W> {
+        HadoopWriter::with_block_size(inner, HADOOP_BLOCK_SIZE)
+    }
+
+    /// Readers with a smaller buffer size than the writer's block size can not read the stream,
+    /// so this should only be used to match a non-default Hadoop configuration.
+    pub fn with_block_size(inner: W, block_size: usize) -> HadoopWriter<W> {
+        assert!(block_size > 0 && block_size <= ::std::u32::MAX as usize);
+        HadoopWriter {
+            inner: Some(inner),
+            block_size: block_size,
+            buf: Vec::with_capacity(block_size),
+            compressed: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Writes any buffered data and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        try!(self.write_block());
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.compressed.clear();
+        try!(compress(&mut Cursor::new(&self.buf[..]), &mut self.compressed));
+        let inner = self.inner.as_mut().unwrap();
+        try!(write_u32_be(inner, self.buf.len() as u32));
+        try!(write_u32_be(inner, self.compressed.len() as u32));
+        try!(inner.write_all(&self.compressed[..]));
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl <W: Write> Write for HadoopWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = cmp::min(data.len(), self.block_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == self.block_size {
+            try!(self.write_block());
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.write_block());
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl <W: Write> Drop for HadoopWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_block();
+        }
+    }
+}
+
+/// Decompresses a Hadoop block stream.
+pub struct HadoopReader<R: Read> {
+    inner: R,
+    block: Vec<u8>,
+    pos: usize,
+    chunk: Vec<u8>,
+}
+
+impl <R: Read> HadoopReader<R> {
+    pub fn new(inner: R) -> HadoopReader<R> {
+        HadoopReader {
+            inner: inner,
+            block: Vec::new(),
+            pos: 0,
+            chunk: Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads and decompresses the next block. Returns false on EOF.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let block_len = match try!(read_u32_be_or_eof(&mut self.inner)) {
+            None    => return Ok(false),
+            Some(n) => n as usize
+        };
+        self.block.clear();
+        self.pos = 0;
+        while self.block.len() < block_len {
+            let chunk_len = try!(read_u32_be(&mut self.inner));
+            try!(read_chunk(&mut self.inner, chunk_len, &mut self.chunk));
+            if try!(peek_uncompressed_length(&self.chunk[..])) as usize > block_len - self.block.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "chunks decompress to more than the block length"));
+            }
+            try!(decompress(&mut Cursor::new(&self.chunk[..]), &mut self.block));
+        }
+        Ok(true)
+    }
+}
+
+impl <R: Read> Read for HadoopReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.block.len() {
+            if !try!(self.read_block()) {
+                return Ok(0);
+            }
+        }
+        let n = cmp::min(buf.len(), self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
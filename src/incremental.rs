@@ -0,0 +1,453 @@
+//! Sans-IO encoding and decoding of the raw format: the caller passes in input as it arrives
+//! and takes the output, without any `Read` or `Write`.
+
+use std::cmp;
+use compress::{compress_block, encode_varint, CompressorOptions, Dict, MIN_BLOCK_SIZE};
+use decompress::{decode_tag, stitch_tag, SnappyError, SnappyWrite, MAX_TAG_LEN};
+use decompress::SnappyError::FormatError;
+use ring::RING_WINDOW_SIZE;
+
+/// What `RawDecoder::feed` did with its input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// All of the input was used, and the stream is not complete yet.
+    NeedInput,
+    /// The stream is complete. Holds how many bytes of the input belonged to it.
+    Done(usize),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum State {
+    // Reading the uncompressed length varint
+    Length { value: u32, shift: u32 },
+    // Between tags, or inside a tag whose first `tag_len` bytes are in `tag`
+    Tag,
+    // Inside a literal, with this many bytes still to come
+    Literal(usize),
+    Done,
+}
+
+/// Decodes a raw stream that is fed to it in pieces of any size.
+///
+/// Decoded output accumulates until it is removed with `drain`. Copies may reach back `window`
+/// bytes, so that much of the drained output is kept; see `with_window`.
+/// After `feed` returns an error, the decoder must not be used again.
+pub struct RawDecoder {
+    state: State,
+    tag: [u8; MAX_TAG_LEN],
+    tag_len: usize,
+    remaining_output: u32,
+    window: usize,
+    // Output still needed for copies, followed by the output that has not been drained
+    buf: Vec<u8>,
+    // Where the output that has not been drained starts in `buf`
+    start: usize,
+}
+
+impl RawDecoder {
+    /// Creates a decoder that allows copies from up to `RING_WINDOW_SIZE` bytes back.
+    pub fn new() -> RawDecoder {
+        RawDecoder::with_window(RING_WINDOW_SIZE)
+    }
+
+    /// Creates a decoder that allows copies from up to `window` bytes back.
+    ///
+    /// Data compressed with `CompressorOptions::window_size` needs at least that window.
+    pub fn with_window(window: usize) -> RawDecoder {
+        RawDecoder {
+            state: State::Length { value: 0, shift: 0 },
+            tag: [0; MAX_TAG_LEN],
+            tag_len: 0,
+            remaining_output: 0,
+            window: window,
+            buf: Vec::new(),
+            start: 0,
+        }
+    }
+
+    /// Decodes as much of `input` as possible. Partial tags and literals at the end of the input
+    /// are kept, and continued by the next call.
+    pub fn feed(&mut self, input: &[u8]) -> Result<Progress, SnappyError> {
+        let mut i = 0;
+        loop {
+            match self.state {
+                State::Length { mut value, mut shift } => {
+                    loop {
+                        if i == input.len() {
+                            self.state = State::Length { value: value, shift: shift };
+                            return Ok(Progress::NeedInput);
+                        }
+                        if shift >= 32 {
+                            return Err(FormatError("uncompressed length exceeds u32::MAX"));
+                        }
+                        let c = input[i];
+                        i += 1;
+                        value |= ((c & 0x7F) as u32) << shift;
+                        if c & 0x80 == 0 {
+                            break;
+                        }
+                        shift += 7;
+                    }
+                    self.remaining_output = value;
+                    self.buf.reserve(cmp::min(value as usize, self.window));
+                    self.state = State::Tag;
+                },
+                State::Tag => {
+                    if self.remaining_output == 0 {
+                        self.state = State::Done;
+                        continue;
+                    }
+                    let (used, tag_size) = stitch_tag(&mut self.tag, &mut self.tag_len, &input[i..]);
+                    i += used;
+                    match tag_size {
+                        None           => return Ok(Progress::NeedInput),
+                        Some(tag_size) => try!(self.decode_tag(tag_size)),
+                    }
+                },
+                State::Literal(remaining) => {
+                    let n = cmp::min(remaining, input.len() - i);
+                    self.buf.extend_from_slice(&input[i..i + n]);
+                    i += n;
+                    if n < remaining {
+                        self.state = State::Literal(remaining - n);
+                        return Ok(Progress::NeedInput);
+                    }
+                    self.state = State::Tag;
+                },
+                State::Done => return Ok(Progress::Done(i)),
+            }
+        }
+    }
+
+    fn decode_tag(&mut self, tag_size: usize) -> Result<(), SnappyError> {
+        let (len, offset) = decode_tag(&self.tag[..tag_size]);
+        if len > self.remaining_output as u64 {
+            return Err(FormatError("output exceeds uncompressed length"));
+        }
+        let len = len as u32;
+        self.remaining_output -= len;
+        match offset {
+            None => self.state = State::Literal(len as usize),
+            Some(0) => return Err(FormatError("zero-length offset")),
+            Some(offset) => {
+                if offset as usize > self.window || offset as usize > self.buf.len() {
+                    return Err(FormatError("copy offset before start of window"));
+                }
+                try!(self.buf.write_from_self(offset, len as u8));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the stream is complete, when there is no more input.
+    pub fn finish(&self) -> Result<(), SnappyError> {
+        match self.state {
+            State::Done             => Ok(()),
+            State::Length { .. }    => Err(FormatError("unterminated uncompressed length")),
+            State::Tag if self.tag_len > 0 => Err(FormatError("EOF while reading tag")),
+            State::Tag              => Err(FormatError("output shorter than uncompressed length")),
+            State::Literal(_)       => Err(FormatError("EOF while reading literal")),
+        }
+    }
+
+    /// Whether the whole stream has been decoded.
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// The decoded output that has not been drained yet.
+    pub fn output(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    /// Removes the first `n` bytes of `output()`.
+    pub fn drain(&mut self, n: usize) {
+        assert!(n <= self.buf.len() - self.start);
+        self.start += n;
+        // Drop history outside the window once that is at least half of the buffer,
+        // so that each byte is moved at most once on average.
+        let unneeded = cmp::min(self.start, self.buf.len().saturating_sub(self.window));
+        if unneeded > 0 && unneeded >= self.buf.len() / 2 {
+            self.buf.drain(..unneeded);
+            self.start -= unneeded;
+        }
+    }
+}
+
+impl Default for RawDecoder {
+    fn default() -> RawDecoder {
+        RawDecoder::new()
+    }
+}
+
+/// Compresses a raw stream that is fed to it in pieces of any size.
+///
+/// The raw format starts with the uncompressed length, so that must be known up front.
+/// Input is collected into blocks of `CompressorOptions::block_size`, each compressed as soon as
+/// it is complete, and the output accumulates until it is removed with `drain` or `read_output`.
+/// The output is the same as `compress_with_options` gives for the whole input in a `Cursor`.
+pub struct RawEncoder {
+    dict: Dict,
+    block_size: usize,
+    window_size: usize,
+    // The block being collected, preceded by the window when there is one
+    block: Vec<u8>,
+    // Where the block being collected starts in `block`
+    block_start: usize,
+    remaining_input: u32,
+    out: Vec<u8>,
+    // Where the output that has not been drained starts in `out`
+    out_start: usize,
+}
+
+impl RawEncoder {
+    /// Creates an encoder for `length` bytes of input.
+    ///
+    /// Fails with `SnappyError::InvalidOptions` if the window size is larger than `MAX_WINDOW_SIZE`.
+    pub fn new(length: u32, options: &CompressorOptions) -> Result<RawEncoder, SnappyError> {
+        try!(options.check());
+        let block_size = cmp::max(options.block_size as usize, MIN_BLOCK_SIZE);
+        let dict = if options.window_size > 0 {
+            Dict::with_window(options.window_size)
+        } else {
+            Dict::new(cmp::min(block_size as u32, length))
+        };
+        let mut ds = [0; 5];
+        let n = encode_varint(length, &mut ds);
+        let out = ds[..n].to_vec();
+        Ok(RawEncoder {
+            dict: dict,
+            block_size: block_size,
+            window_size: options.window_size as usize,
+            block: Vec::new(),
+            block_start: 0,
+            remaining_input: length,
+            out: out,
+            out_start: 0,
+        })
+    }
+
+    /// Takes as much of `input` as belongs to the stream, and returns how much that was.
+    pub fn feed(&mut self, input: &[u8]) -> usize {
+        let n = cmp::min(input.len(), self.remaining_input as usize);
+        let mut input = &input[..n];
+        while !input.is_empty() {
+            if self.block.len() == self.block_start && self.window_size > 0 && self.block.len() > 2 * self.window_size {
+                // Same as `compress_with_window`
+                let shift = self.block.len() - self.window_size;
+                self.block.drain(..shift);
+                self.block_start -= shift;
+                self.dict.rebase(shift as u32);
+            }
+            let take = cmp::min(input.len(), self.block_size - (self.block.len() - self.block_start));
+            self.block.extend_from_slice(&input[..take]);
+            input = &input[take..];
+            self.remaining_input -= take as u32;
+            if self.block.len() - self.block_start == self.block_size || self.remaining_input == 0 {
+                self.compress_block();
+            }
+        }
+        n
+    }
+
+    fn compress_block(&mut self) {
+        compress_block(&self.block[..], self.block_start, &mut self.out, &mut self.dict);
+        if self.window_size > 0 {
+            self.block_start = self.block.len();
+        } else {
+            self.block.clear();
+            self.dict.clear();
+        }
+    }
+
+    /// Whether all of the input has been fed and compressed.
+    pub fn is_done(&self) -> bool {
+        self.remaining_input == 0
+    }
+
+    pub(crate) fn hash_collisions(&self) -> u64 {
+        self.dict.hash_collisions()
+    }
+
+    /// The compressed output that has not been drained yet.
+    pub fn output(&self) -> &[u8] {
+        &self.out[self.out_start..]
+    }
+
+    /// Removes the first `n` bytes of `output()`.
+    pub fn drain(&mut self, n: usize) {
+        assert!(n <= self.out.len() - self.out_start);
+        self.out_start += n;
+        if self.out_start == self.out.len() {
+            self.out.clear();
+            self.out_start = 0;
+        }
+    }
+
+    /// Moves as much of the output as fits into `buf`, and returns how much that was.
+    pub fn read_output(&mut self, buf: &mut [u8]) -> usize {
+        let n = cmp::min(buf.len(), self.out.len() - self.out_start);
+        buf[..n].copy_from_slice(&self.out[self.out_start..self.out_start + n]);
+        self.drain(n);
+        n
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use compress::{compress_with_options, CompressorOptions};
+    use decompress::{decompress, SnappyError};
+    use super::{Progress, RawDecoder, RawEncoder};
+
+    /// Feeds `input` in pieces of `chunk` bytes, draining the output after each.
+    fn decode_chunked(input: &[u8], chunk: usize, window: usize) -> Result<Vec<u8>, ()> {
+        let mut decoder = RawDecoder::with_window(window);
+        let mut out = Vec::new();
+        for piece in input.chunks(chunk) {
+            let progress = try!(decoder.feed(piece).map_err(|_| ()));
+            out.extend_from_slice(decoder.output());
+            let n = decoder.output().len();
+            decoder.drain(n);
+            if let Progress::Done(used) = progress {
+                // Trailing data after the stream is an error for `decompress`
+                if used < piece.len() {
+                    return Err(());
+                }
+            }
+        }
+        try!(decoder.finish().map_err(|_| ()));
+        Ok(out)
+    }
+
+    fn compress(input: &[u8], block_size: u16, window_size: u32) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let options = CompressorOptions { block_size: block_size, window_size: window_size };
+        compress_with_options(&mut Cursor::new(input), &mut compressed, &options).unwrap();
+        compressed
+    }
+
+    #[test]
+    fn test_raw_decoder_chunks() {
+        let text = include_bytes!("../tests/moonstone-short.txt");
+        let repeated: Vec<u8> = (0..200_000u32).map(|i| (i / 3 % 256) as u8 ^ (i >> 10) as u8).collect();
+        for &input in [&text[..], &repeated[..], &[], &[7; 70000]].iter() {
+            for &block_size in [16, 4096, 65535].iter() {
+                let compressed = compress(input, block_size, 0);
+                for &chunk in [1, 2, 3, 5, 64, 1000, 1 << 20].iter() {
+                    let out = decode_chunked(&compressed[..], chunk, 1 << 16).unwrap();
+                    assert!(out == input, "block size {}, chunk {}", block_size, chunk);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_raw_decoder_window() {
+        let part: Vec<u8> = (0..80_000u32).map(|i| (i * 7919 ^ i >> 5) as u8).collect();
+        let input: Vec<u8> = part.iter().cycle().take(300_000).cloned().collect();
+        let compressed = compress(&input[..], 65535, 100_000);
+        assert!(decode_chunked(&compressed[..], 1000, 100_000).unwrap() == input);
+        assert!(decode_chunked(&compressed[..], 1000, 1 << 16).is_err());
+    }
+
+    #[test]
+    fn test_raw_decoder_same_as_decompress() {
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![13, 0x08, 1, 2, 3, 0x26, 3, 0],
+            vec![13, 0x08, 1, 2, 3, 0x27, 3, 0, 0, 0],
+            vec![13, 0x08, 1, 2, 3, 0x27, 4, 0, 0, 0],
+            vec![13, 0x08, 1, 2, 3, 0x27, 0, 0, 0, 0],
+            vec![13, 0x08, 1, 2, 3, 0x26, 3, 0, 0],
+            vec![13, 0x08, 1, 2, 3, 0x26, 3],
+            vec![3, 0xF0, 1, 2, 3],
+            vec![3, 0xFC, 0xFF, 0xFF, 0xFF, 0xFF, 1, 2, 3],
+            vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+            vec![0xFF],
+            vec![0],
+            vec![1, 0x01, 0x01],
+            vec![],
+        ];
+        for input in inputs.iter() {
+            let mut expected = Vec::new();
+            let expected = decompress(&mut Cursor::new(&input[..]), &mut expected).map(|_| expected).map_err(|_| ());
+            for &chunk in [1, 2, 3, 100].iter() {
+                assert_eq!(decode_chunked(&input[..], chunk, 1 << 16), expected, "input {:?}, chunk {}", input, chunk);
+            }
+        }
+    }
+
+    #[test]
+    fn test_raw_decoder_done() {
+        let mut decoder = RawDecoder::new();
+        assert_eq!(decoder.feed(&[3, 0x08, 1]).unwrap(), Progress::NeedInput);
+        assert!(!decoder.is_done());
+        assert!(decoder.finish().is_err());
+        assert_eq!(decoder.feed(&[2, 3, 9, 9]).unwrap(), Progress::Done(2));
+        assert!(decoder.is_done());
+        decoder.finish().unwrap();
+        assert_eq!(decoder.output(), &[1, 2, 3]);
+        decoder.drain(1);
+        assert_eq!(decoder.output(), &[2, 3]);
+        assert_eq!(decoder.feed(&[9]).unwrap(), Progress::Done(0));
+    }
+
+    /// Feeds `input` in pieces of `chunk` bytes, reading the output into buffers of `out_chunk` bytes.
+    fn encode_chunked(input: &[u8], chunk: usize, out_chunk: usize, options: &CompressorOptions) -> Vec<u8> {
+        let mut encoder = RawEncoder::new(input.len() as u32, options).unwrap();
+        let mut out = Vec::new();
+        let mut buf = vec![0; out_chunk];
+        for piece in input.chunks(chunk) {
+            assert_eq!(encoder.feed(piece), piece.len());
+            loop {
+                let n = encoder.read_output(&mut buf[..]);
+                if n == 0 {
+                    break;
+                }
+                out.extend_from_slice(&buf[..n]);
+            }
+        }
+        assert!(encoder.is_done());
+        out.extend_from_slice(encoder.output());
+        out
+    }
+
+    #[test]
+    fn test_raw_encoder_same_as_compress() {
+        let text = include_bytes!("../tests/moonstone-short.txt");
+        let part: Vec<u8> = (0..80_000u32).map(|i| (i * 7919 ^ i >> 5) as u8).collect();
+        let repeated: Vec<u8> = part.iter().cycle().take(300_000).cloned().collect();
+        for &input in [&text[..], &repeated[..], &[], &[7; 10]].iter() {
+            for &(block_size, window_size) in [(16, 0), (4096, 0), (65535, 0), (4096, 100_000), (65535, 1 << 16)].iter() {
+                let options = CompressorOptions { block_size: block_size, window_size: window_size };
+                let expected = compress(input, block_size, window_size);
+                for &(chunk, out_chunk) in [(1, 1), (7, 100), (5000, 3), (1 << 20, 1 << 20)].iter() {
+                    if chunk == 1 && input.len() > 10_000 {
+                        continue;
+                    }
+                    let out = encode_chunked(input, chunk, out_chunk, &options);
+                    assert!(out == expected, "block size {}, window {}, chunk {}", block_size, window_size, chunk);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_raw_encoder_length() {
+        let mut encoder = RawEncoder::new(5, &Default::default()).unwrap();
+        assert_eq!(encoder.feed(b"abc"), 3);
+        assert!(!encoder.is_done());
+        assert_eq!(encoder.output(), &[5]);
+        encoder.drain(1);
+        assert_eq!(encoder.feed(b"defgh"), 2);
+        assert!(encoder.is_done());
+        assert_eq!(encoder.feed(b"ijk"), 0);
+        assert_eq!(encoder.output(), &[4 << 2, b'a', b'b', b'c', b'd', b'e']);
+
+        let options = CompressorOptions { window_size: ::MAX_WINDOW_SIZE + 1, ..Default::default() };
+        match RawEncoder::new(5, &options) {
+            Err(SnappyError::InvalidOptions(_)) => {},
+            _ => panic!("window size larger than MAX_WINDOW_SIZE accepted")
+        }
+    }
+}
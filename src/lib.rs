@@ -1,7 +1,38 @@
+#![cfg_attr(feature = "safe", forbid(unsafe_code))]
+
+#[cfg(feature = "bytes")]
+extern crate bytes;
+
 mod decompress;
 mod compress;
+mod match_length;
+mod dictionary;
+mod ring;
+mod incremental;
+mod stats;
+mod tags;
+mod auto;
+mod crc;
+mod framing;
+mod hadoop;
+mod xerial;
 mod util;
+#[cfg(feature = "bytes")]
+mod bytes_io;
+#[cfg(not(feature = "safe"))]
 mod zero_array;
 
-pub use compress::{compress, compress_with_options, CompressorOptions, SnappyRead, MAX_BLOCK_SIZE};
-pub use decompress::{decompress, SnappyWrite};
\ No newline at end of file
+pub use compress::{compress, compress_with_options, max_compressed_length, CompressorOptions, SnappyRead, MAX_BLOCK_SIZE,
+                   MAX_WINDOW_SIZE};
+pub use decompress::{decompress, SnappyWrite, SnappyError};
+pub use ring::{decompress_with_window, RingWriter, RING_WINDOW_SIZE};
+pub use tags::{disassemble, CopyKind, Disassembly, StreamBuilder, Tag};
+pub use stats::{compress_with_stats, CompressionStats};
+pub use incremental::{Progress, RawDecoder, RawEncoder};
+pub use dictionary::{compress_with_dictionary, decompress_with_dictionary, train_dictionary, MAX_DICTIONARY_SIZE};
+pub use auto::{decompress_auto, detect_format, stream_info, AutoDecoder, Format, StreamInfo, DETECT_PREFIX_LEN};
+pub use framing::{FramedReader, FramedWriter, FRAMING_MAGIC};
+pub use hadoop::{HadoopReader, HadoopWriter, HADOOP_BLOCK_SIZE};
+#[cfg(feature = "bytes")]
+pub use bytes_io::{compress_to_bytes, decompress_to_bytes};
+pub use xerial::{XerialReader, XerialWriter, XERIAL_BLOCK_SIZE, XERIAL_MAGIC};
\ No newline at end of file
@@ -0,0 +1,317 @@
+use std::cmp;
+use std::convert::TryInto;
+#[cfg(not(feature = "safe"))]
+use std::mem;
+#[cfg(not(feature = "safe"))]
+use std::ptr;
+
+#[cfg(not(feature = "safe"))]
+const WORD_SIZE: usize = mem::size_of::<usize>();
+
+/// Returns the length of the common prefix of `s1` and `s2`.
+///
+/// The safe counterpart of `find_match_length`, comparing 8 bytes at a time.
+/// Used instead of it when the `safe` feature is enabled.
+#[cfg_attr(not(feature = "safe"), allow(dead_code))]
+pub fn find_match_length_safe(s1: &[u8], s2: &[u8]) -> u32 {
+    let len = cmp::min(s1.len(), s2.len());
+    let mut matched = 0;
+    while len - matched >= 8 {
+        let a = u64::from_le_bytes(s1[matched..matched + 8].try_into().unwrap());
+        let b = u64::from_le_bytes(s2[matched..matched + 8].try_into().unwrap());
+        let x = a ^ b;
+        if x != 0 {
+            return (matched as u32) + x.trailing_zeros() / 8;
+        }
+        matched += 8;
+    }
+    while matched < len && s1[matched] == s2[matched] {
+        matched += 1;
+    }
+    matched as u32
+}
+
+/// Returns the length of the common prefix of the byte sequences starting at `s1` and `s2`.
+///
+/// Uses AVX2 or SSE2 comparisons when the CPU supports them, falling back to comparing
+/// one machine word at a time.
+// Does not read *s2_limit or beyond.
+// Does not read *(s1 + (s2_limit - s2)) or beyond.
+// Requires that s2_limit >= s2.
+#[cfg(not(feature = "safe"))]
+#[inline]
+pub unsafe fn find_match_length(s1: *const u8, s2: *const u8, s2_limit: *const u8) -> u32 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        let remaining = s2_limit as usize - s2 as usize;
+        let simd = x86::simd_level();
+        if remaining >= 32 && simd == x86::AVX2 {
+            return x86::find_match_length_avx2(s1, s2, s2_limit);
+        }
+        if remaining >= 16 && simd >= x86::SSE2 {
+            return x86::find_match_length_sse2(s1, s2, s2_limit);
+        }
+    }
+    find_match_length_word(s1, s2, s2_limit)
+}
+
+#[cfg(not(feature = "safe"))]
+#[cfg(target_endian = "little")]
+#[inline]
+unsafe fn find_match_length_word(s1: *const u8, s2: *const u8, s2_limit: *const u8) -> u32 {
+    find_match_length_le(s1, s2, s2_limit)
+}
+
+#[cfg(not(feature = "safe"))]
+#[cfg(target_endian = "big")]
+#[inline]
+unsafe fn find_match_length_word(s1: *const u8, s2: *const u8, s2_limit: *const u8) -> u32 {
+    find_match_length_be(s1, s2, s2_limit)
+}
+
+#[cfg(not(feature = "safe"))]
+unsafe fn load_word(p: *const u8) -> usize { ptr::read_unaligned(p as *const usize) }
+
+// Largely borrowed from the reference Snappy implementation.
+// The words are interpreted as little endian, so the first differing byte is found
+// among the lowest bits of the xor.
+#[cfg(not(feature = "safe"))]
+#[cfg_attr(target_endian = "big", allow(dead_code))]
+unsafe fn find_match_length_le(s1: *const u8, mut s2: *const u8, s2_limit: *const u8) -> u32 {
+    let mut matched = 0;
+    while s2_limit as usize - s2 as usize >= WORD_SIZE {
+        let x = usize::from_le(load_word(s2)) ^ usize::from_le(load_word(s1.offset(matched as isize)));
+        if x == 0 {
+            s2 = s2.offset(WORD_SIZE as isize);
+            matched += WORD_SIZE as u32;
+        } else {
+            // First non-zero bit is first non-matching bit
+            return matched + x.trailing_zeros() / 8;
+        }
+    }
+    matched + find_match_length_bytes(s1.offset(matched as isize), s2, s2_limit)
+}
+
+// Same as find_match_length_le, but with the words interpreted as big endian,
+// so the first differing byte is found among the highest bits of the xor.
+// This is what big endian targets use, but it is correct (if slower) everywhere.
+#[cfg(not(feature = "safe"))]
+#[cfg_attr(target_endian = "little", allow(dead_code))]
+unsafe fn find_match_length_be(s1: *const u8, mut s2: *const u8, s2_limit: *const u8) -> u32 {
+    let mut matched = 0;
+    while s2_limit as usize - s2 as usize >= WORD_SIZE {
+        let x = usize::from_be(load_word(s2)) ^ usize::from_be(load_word(s1.offset(matched as isize)));
+        if x == 0 {
+            s2 = s2.offset(WORD_SIZE as isize);
+            matched += WORD_SIZE as u32;
+        } else {
+            return matched + x.leading_zeros() / 8;
+        }
+    }
+    matched + find_match_length_bytes(s1.offset(matched as isize), s2, s2_limit)
+}
+
+#[cfg(not(feature = "safe"))]
+#[inline]
+unsafe fn find_match_length_bytes(s1: *const u8, mut s2: *const u8, s2_limit: *const u8) -> u32 {
+    let mut matched = 0;
+    while s2 < s2_limit && *s1.offset(matched as isize) == *s2 {
+        s2 = s2.offset(1);
+        matched += 1;
+    }
+    matched
+}
+
+#[cfg(not(feature = "safe"))]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    const UNKNOWN: u8 = 0;
+    pub const NONE: u8 = 1;
+    pub const SSE2: u8 = 2;
+    pub const AVX2: u8 = 3;
+
+    /// The best instruction set found by `simd_level`, detected on first use.
+    static SIMD_LEVEL: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+    /// Returns the best of `NONE`, `SSE2` and `AVX2` that the CPU supports.
+    ///
+    /// Detection is done once, since this is called for every match the compressor tries.
+    #[inline]
+    pub fn simd_level() -> u8 {
+        let level = SIMD_LEVEL.load(Ordering::Relaxed);
+        if level != UNKNOWN {
+            return level;
+        }
+        let level = if is_x86_feature_detected!("avx2") {
+            AVX2
+        } else if is_x86_feature_detected!("sse2") {
+            SSE2
+        } else {
+            NONE
+        };
+        SIMD_LEVEL.store(level, Ordering::Relaxed);
+        level
+    }
+
+    // Each lane of the comparison becomes one bit of the mask, so the first
+    // zero bit of the mask is the first non-matching byte.
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn find_match_length_avx2(s1: *const u8, mut s2: *const u8, s2_limit: *const u8) -> u32 {
+        let mut matched = 0;
+        while s2_limit as usize - s2 as usize >= 32 {
+            let a = _mm256_loadu_si256(s2 as *const __m256i);
+            let b = _mm256_loadu_si256(s1.offset(matched as isize) as *const __m256i);
+            let mask = _mm256_movemask_epi8(_mm256_cmpeq_epi8(a, b)) as u32;
+            if mask != 0xFFFF_FFFF {
+                return matched + (!mask).trailing_zeros();
+            }
+            s2 = s2.offset(32);
+            matched += 32;
+        }
+        matched + find_match_length_sse2(s1.offset(matched as isize), s2, s2_limit)
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn find_match_length_sse2(s1: *const u8, mut s2: *const u8, s2_limit: *const u8) -> u32 {
+        let mut matched = 0;
+        while s2_limit as usize - s2 as usize >= 16 {
+            let a = _mm_loadu_si128(s2 as *const __m128i);
+            let b = _mm_loadu_si128(s1.offset(matched as isize) as *const __m128i);
+            let mask = _mm_movemask_epi8(_mm_cmpeq_epi8(a, b)) as u32;
+            if mask != 0xFFFF {
+                return matched + (!mask).trailing_zeros();
+            }
+            s2 = s2.offset(16);
+            matched += 16;
+        }
+        matched + super::find_match_length_word(s1.offset(matched as isize), s2, s2_limit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::find_match_length_safe;
+    #[cfg(not(feature = "safe"))]
+    use super::{find_match_length, find_match_length_le, find_match_length_be};
+
+    #[cfg(not(feature = "safe"))]
+    type MatchFn = unsafe fn(*const u8, *const u8, *const u8) -> u32;
+
+    /// Checks `f` against a naive implementation for all common prefix lengths of
+    /// inputs of length `0..max_len`.
+    fn check_slices<F: Fn(&[u8], &[u8]) -> u32>(f: F, max_len: usize) {
+        for len in 0..max_len {
+            for mismatch in 0..len + 1 {
+                let s1: Vec<u8> = (0..len).map(|i| (i * 7 % 251) as u8).collect();
+                let mut s2 = s1.clone();
+                if mismatch < len {
+                    s2[mismatch] ^= 0x10;
+                }
+                assert_eq!(f(&s1[..], &s2[..]) as usize, mismatch, "len={} mismatch={}", len, mismatch);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "safe"))]
+    fn check(f: MatchFn, max_len: usize) {
+        check_slices(|s1, s2| unsafe { f(s1.as_ptr(), s2.as_ptr(), s2.as_ptr().offset(s2.len() as isize)) }, max_len);
+    }
+
+    #[test]
+    fn test_find_match_length_safe() {
+        check_slices(find_match_length_safe, 100);
+    }
+
+    #[test]
+    fn test_find_match_length_safe_different_lengths() {
+        assert_eq!(find_match_length_safe(&[1, 2, 3], &[1, 2, 3, 4]), 3);
+        assert_eq!(find_match_length_safe(&[1; 20], &[1; 12]), 12);
+    }
+
+    #[cfg(not(feature = "safe"))]
+    #[test]
+    fn test_find_match_length() {
+        check(find_match_length, 100);
+    }
+
+    #[cfg(not(feature = "safe"))]
+    #[test]
+    fn test_find_match_length_le() {
+        check(find_match_length_le, 40);
+    }
+
+    #[cfg(not(feature = "safe"))]
+    #[test]
+    fn test_find_match_length_be() {
+        check(find_match_length_be, 40);
+    }
+
+    #[cfg(all(not(feature = "safe"), any(target_arch = "x86", target_arch = "x86_64")))]
+    #[test]
+    fn test_simd_level() {
+        use super::x86::{simd_level, AVX2, NONE, SSE2};
+        let expected = if is_x86_feature_detected!("avx2") {
+            AVX2
+        } else if is_x86_feature_detected!("sse2") {
+            SSE2
+        } else {
+            NONE
+        };
+        assert_eq!(simd_level(), expected);
+        // Now from the cache
+        assert_eq!(simd_level(), expected);
+    }
+
+    #[cfg(all(not(feature = "safe"), any(target_arch = "x86", target_arch = "x86_64")))]
+    #[test]
+    fn test_find_match_length_sse2() {
+        if is_x86_feature_detected!("sse2") {
+            check(super::x86::find_match_length_sse2, 100);
+        }
+    }
+
+    #[cfg(all(not(feature = "safe"), any(target_arch = "x86", target_arch = "x86_64")))]
+    #[test]
+    fn test_find_match_length_avx2() {
+        if is_x86_feature_detected!("avx2") {
+            check(super::x86::find_match_length_avx2, 100);
+        }
+    }
+
+    #[cfg(not(feature = "safe"))]
+    #[test]
+    fn test_find_match_length_overlapping() {
+        // Matches in the compressor may overlap the data they are compared against.
+        let data = [5u8; 70];
+        let got = unsafe { find_match_length(data.as_ptr(), data.as_ptr().offset(3), data.as_ptr().offset(70)) };
+        assert_eq!(got, 67);
+    }
+
+    #[test]
+    fn test_find_match_length_safe_overlapping() {
+        let data = [5u8; 70];
+        assert_eq!(find_match_length_safe(&data[..], &data[3..]), 67);
+    }
+
+    #[cfg(not(feature = "safe"))]
+    #[test]
+    fn test_find_match_length_safe_same_as_fast() {
+        // Random-ish data with many short matches, compared at every pair of positions.
+        let data: Vec<u8> = (0..300u32).map(|i| (i.wrapping_mul(2654435761) >> 29) as u8).collect();
+        for a in 0..data.len() {
+            for b in a..data.len() {
+                let fast = unsafe {
+                    find_match_length(data[a..].as_ptr(), data[b..].as_ptr(), data.as_ptr().offset(data.len() as isize))
+                };
+                assert_eq!(find_match_length_safe(&data[a..], &data[b..]), fast, "a={} b={}", a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Decompression to any `io::Write`, keeping only the most recent output in memory.
+
+use std::io::{BufRead, Write};
+use std::io;
+use std::cmp;
+use decompress::{decompress, SnappyError, SnappyWrite};
+
+/// The window of `RingWriter::new`.
+///
+/// This covers everything our compressor produces without `CompressorOptions::window_size`,
+/// since it then never copies across blocks and a block is at most `MAX_BLOCK_SIZE` bytes.
+/// The same holds for the reference implementation.
+pub const RING_WINDOW_SIZE: usize = 64 * 1024;
+
+/// The smallest ring, so that small windows do not mean small writes to the underlying writer.
+const MIN_RING_SIZE: usize = 4096;
+
+/// Keeps the last `window` bytes of output in a ring buffer, for copies to refer to,
+/// and writes the output to the underlying writer whenever the buffer is full.
+///
+/// A copy from further back than the window fails with `ErrorKind::InvalidData`.
+/// The buffered output is written by `flush`, `into_inner` or when the writer is dropped.
+pub struct RingWriter<W: Write> {
+    inner: Option<W>,
+    ring: Vec<u8>,
+    window: usize,
+    // Where the next byte goes in `ring`
+    pos: usize,
+    // How many bytes of `ring` hold output
+    filled: usize,
+    // How many bytes before `pos` have not been written to `inner` yet
+    pending: usize,
+}
+
+impl <W: Write> RingWriter<W> {
+    /// Creates a writer with a window of `RING_WINDOW_SIZE`.
+    pub fn new(inner: W) -> RingWriter<W> {
+        RingWriter::with_window(inner, RING_WINDOW_SIZE)
+    }
+
+    /// Creates a writer that allows copies from up to `window` bytes back.
+    ///
+    /// Data compressed with `CompressorOptions::window_size` needs at least that window.
+    pub fn with_window(inner: W, window: usize) -> RingWriter<W> {
+        RingWriter {
+            inner: Some(inner),
+            ring: vec![0; cmp::max(window, MIN_RING_SIZE)],
+            window: window,
+            pos: 0,
+            filled: 0,
+            pending: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Writes any buffered output and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        try!(self.write_pending());
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        let inner = self.inner.as_mut().unwrap();
+        if self.pending <= self.pos {
+            try!(inner.write_all(&self.ring[self.pos - self.pending..self.pos]));
+        } else {
+            let start = self.ring.len() - (self.pending - self.pos);
+            try!(inner.write_all(&self.ring[start..]));
+            try!(inner.write_all(&self.ring[..self.pos]));
+        }
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// Makes room for at least one byte at `pos`.
+    fn make_room(&mut self) -> io::Result<()> {
+        if self.pending == self.ring.len() {
+            try!(self.write_pending());
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.pos += n;
+        if self.pos == self.ring.len() {
+            self.pos = 0;
+        }
+        self.filled = cmp::min(self.filled + n, self.ring.len());
+        self.pending += n;
+    }
+}
+
+impl <W: Write> Write for RingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        try!(self.make_room());
+        let n = cmp::min(data.len(), cmp::min(self.ring.len() - self.pos, self.ring.len() - self.pending));
+        self.ring[self.pos..self.pos + n].copy_from_slice(&data[..n]);
+        self.advance(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.write_pending());
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl <W: Write> SnappyWrite for RingWriter<W> {
+    fn write_from_self(&mut self, offset: u32, len: u8) -> io::Result<()> {
+        let offset = offset as usize;
+        if offset == 0 || offset > cmp::min(self.filled, self.window) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "copy offset before start of window"));
+        }
+        // Byte by byte, since the copy may overlap its own output or wrap around the ring.
+        for _ in 0..len {
+            try!(self.make_room());
+            let src = if offset <= self.pos { self.pos - offset } else { self.pos + self.ring.len() - offset };
+            self.ring[self.pos] = self.ring[src];
+            self.advance(1);
+        }
+        Ok(())
+    }
+
+    fn set_uncompressed_length(&mut self, _length: u32) {
+    }
+}
+
+impl <W: Write> Drop for RingWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_pending();
+        }
+    }
+}
+
+/// Decompresses a raw stream to `writer` as it is decoded, keeping only the last `window` bytes
+/// of output in memory.
+///
+/// Fails if a copy reaches further back than `window`, which never happens with a window of
+/// `RING_WINDOW_SIZE` or more unless the data was compressed with a larger
+/// `CompressorOptions::window_size`. Output before the error has already been written.
+pub fn decompress_with_window<R: BufRead, W: Write>(reader: &mut R, writer: &mut W,
+                                                   window: usize) -> Result<(), SnappyError> {
+    let mut ring = RingWriter::with_window(writer, window);
+    try!(decompress(reader, &mut ring));
+    try!(ring.into_inner());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+    use compress::{compress_with_options, CompressorOptions};
+    use decompress::{decompress, SnappyWrite};
+    use super::{decompress_with_window, RingWriter, RING_WINDOW_SIZE};
+
+    #[test]
+    fn test_ring_write_from_self() {
+        let mut w = RingWriter::new(Vec::new());
+        assert!(w.write_from_self(1, 1).is_err());
+        w.write_all(&[1, 2, 3]).unwrap();
+        w.write_from_self(3, 2).unwrap();
+        w.write_from_self(2, 4).unwrap();
+        assert!(w.write_from_self(10, 1).is_err());
+        assert!(w.write_from_self(0, 1).is_err());
+        assert_eq!(w.into_inner().unwrap(), vec![1, 2, 3, 1, 2, 1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn test_ring_wraps() {
+        // Copies across the end of the ring, from bytes that have already been written out.
+        let data: Vec<u8> = (0..RING_WINDOW_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        let mut expected = data.clone();
+        let mut w = RingWriter::new(Vec::new());
+        w.write_all(&data[..]).unwrap();
+        for &(offset, len) in [(RING_WINDOW_SIZE, 64), (200, 64), (RING_WINDOW_SIZE - 1, 3), (1, 10)].iter() {
+            w.write_from_self(offset as u32, len).unwrap();
+            for _ in 0..len {
+                let c = expected[expected.len() - offset];
+                expected.push(c);
+            }
+        }
+        assert!(w.write_from_self(RING_WINDOW_SIZE as u32 + 1, 1).is_err());
+        assert!(w.into_inner().unwrap() == expected);
+    }
+
+    #[test]
+    fn test_ring_decompress() {
+        let input: Vec<u8> = (0..300_000u32).map(|i| (i / 7 % 256) as u8 ^ (i >> 12) as u8).collect();
+        for &block_size in [16, 4096, 65535].iter() {
+            let mut compressed = Vec::new();
+            let options = CompressorOptions { block_size: block_size, ..Default::default() };
+            compress_with_options(&mut Cursor::new(&input[..]), &mut compressed, &options).unwrap();
+            let mut w = RingWriter::new(Vec::new());
+            decompress(&mut Cursor::new(&compressed[..]), &mut w).unwrap();
+            assert!(w.into_inner().unwrap() == input, "block size {}", block_size);
+        }
+    }
+
+    #[test]
+    fn test_ring_drop() {
+        let mut out = Vec::new();
+        {
+            let mut w = RingWriter::new(&mut out);
+            w.write_all(b"abc").unwrap();
+            w.write_from_self(3, 3).unwrap();
+            assert!(w.get_ref().is_empty());
+        }
+        assert_eq!(&out[..], b"abcabc");
+    }
+
+    #[test]
+    fn test_ring_small_window() {
+        let mut w = RingWriter::with_window(Vec::new(), 4);
+        w.write_all(b"abcdef").unwrap();
+        w.write_from_self(4, 3).unwrap();
+        assert!(w.write_from_self(5, 1).is_err());
+        assert_eq!(&w.into_inner().unwrap()[..], b"abcdefcde");
+
+        let mut w = RingWriter::with_window(Vec::new(), 0);
+        w.write_all(b"abc").unwrap();
+        assert!(w.write_from_self(1, 1).is_err());
+        assert_eq!(&w.into_inner().unwrap()[..], b"abc");
+    }
+
+    #[test]
+    fn test_decompress_with_window() {
+        let part: Vec<u8> = (0..80_000u32).map(|i| (i * 7919 ^ i >> 5) as u8).collect();
+        let mut input = part.clone();
+        input.extend_from_slice(&part[..]);
+        input.extend_from_slice(&part[..]);
+
+        // Without a compressor window, a window of one block is enough.
+        for &window in [65535, RING_WINDOW_SIZE, 1 << 20].iter() {
+            let mut out = Vec::new();
+            decompress_with_window(&mut Cursor::new(&compress(&input[..], 0)[..]), &mut out, window).unwrap();
+            assert!(out == input, "window {}", window);
+        }
+
+        // The repeats are further back than the default window, so this one is needed.
+        let compressed = compress(&input[..], 100_000);
+        for &window in [100_000, 1 << 20].iter() {
+            let mut out = Vec::new();
+            decompress_with_window(&mut Cursor::new(&compressed[..]), &mut out, window).unwrap();
+            assert!(out == input, "window {}", window);
+        }
+        let mut out = Vec::new();
+        assert!(decompress_with_window(&mut Cursor::new(&compressed[..]), &mut out, RING_WINDOW_SIZE).is_err());
+    }
+
+    fn compress(input: &[u8], window_size: u32) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        let options = CompressorOptions { window_size: window_size, ..Default::default() };
+        compress_with_options(&mut Cursor::new(input), &mut compressed, &options).unwrap();
+        compressed
+    }
+}
@@ -0,0 +1,200 @@
+//! Statistics about what the compressor produces, for tuning `CompressorOptions`.
+
+use std::cmp;
+use std::io::Write;
+use std::io;
+use compress::{CompressorOptions, SnappyRead, MIN_BLOCK_SIZE};
+use incremental::RawEncoder;
+use tags::{CopyKind, Disassembly, Tag};
+
+/// What `compress_with_stats` produced.
+///
+/// A match is a repeat found by the compressor. It is emitted as one or more copy tags, since a
+/// copy tag holds at most 64 bytes. The histograms have power of two buckets: `match_lengths[i]`
+/// counts the matches of length `2^i` to `2^(i+1) - 1`, and likewise for `match_offsets`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// The number of literal tags.
+    pub literals: u64,
+    /// The number of bytes in literal tags.
+    pub literal_bytes: u64,
+    /// The number of copy tags with 1 byte offsets.
+    pub copies_1_byte_offset: u64,
+    /// The number of copy tags with 2 byte offsets.
+    pub copies_2_byte_offset: u64,
+    /// The number of copy tags with 4 byte offsets, used only with `CompressorOptions::window_size`.
+    pub copies_4_byte_offset: u64,
+    pub match_lengths: [u64; 16],
+    pub match_offsets: [u64; 31],
+    /// How often a prefix replaced another with the same hash in the compressor's hash table,
+    /// which loses the chance to match the replaced one.
+    pub hash_collisions: u64,
+    /// The block size used, which is `CompressorOptions::block_size` rounded up to at least 16.
+    pub block_size: usize,
+    /// The uncompressed and compressed size of each block, in order.
+    pub blocks: Vec<(usize, usize)>,
+}
+
+impl CompressionStats {
+    /// Counts the tags of one compressed block.
+    fn add_block(&mut self, uncompressed: usize, compressed: &[u8]) {
+        self.blocks.push((uncompressed, compressed.len()));
+        // The current match, as (offset, length), continued by copy tags with the same offset
+        let mut current: Option<(u32, u32)> = None;
+        for tag in Disassembly::tags(compressed) {
+            match tag.expect("compressor output is valid") {
+                Tag::Literal { len, .. } => {
+                    self.literals += 1;
+                    self.literal_bytes += len as u64;
+                    if let Some(m) = current.take() {
+                        self.add_match(m);
+                    }
+                },
+                Tag::Copy { offset, len, tag_kind } => {
+                    match tag_kind {
+                        CopyKind::OneByteOffset  => self.copies_1_byte_offset += 1,
+                        CopyKind::TwoByteOffset  => self.copies_2_byte_offset += 1,
+                        CopyKind::FourByteOffset => self.copies_4_byte_offset += 1,
+                    }
+                    current = match current {
+                        Some((o, l)) if o == offset => Some((o, l + len as u32)),
+                        Some(m) => { self.add_match(m); Some((offset, len as u32)) },
+                        None    => Some((offset, len as u32)),
+                    };
+                }
+            }
+        }
+        if let Some(m) = current {
+            self.add_match(m);
+        }
+    }
+
+    fn add_match(&mut self, (offset, len): (u32, u32)) {
+        self.match_lengths[cmp::min(log2(len), self.match_lengths.len() - 1)] += 1;
+        self.match_offsets[cmp::min(log2(offset), self.match_offsets.len() - 1)] += 1;
+    }
+}
+
+fn log2(n: u32) -> usize {
+    31 - n.leading_zeros() as usize
+}
+
+/// Compresses like `compress_with_options`, and also returns statistics about the output.
+///
+/// This is slower than `compress_with_options`, since every block is parsed again.
+pub fn compress_with_stats<R: SnappyRead, W: Write>(inp: &mut R, out: &mut W,
+                                                    options: &CompressorOptions) -> io::Result<CompressionStats> {
+    let length = try!(inp.available()) as u32;
+    let mut encoder = try!(RawEncoder::new(length, options));
+    try!(out.write_all(encoder.output()));
+    let n = encoder.output().len();
+    encoder.drain(n);
+
+    let block_size = cmp::max(options.block_size as usize, MIN_BLOCK_SIZE);
+    let mut stats = CompressionStats { block_size: block_size, ..Default::default() };
+    let mut in_block = 0;
+    while !encoder.is_done() {
+        let n = {
+            let buf = try!(inp.fill_buf());
+            if buf.is_empty() {
+                break;
+            }
+            encoder.feed(&buf[..cmp::min(buf.len(), block_size - in_block)])
+        };
+        inp.consume(n);
+        in_block += n;
+        if in_block == block_size || encoder.is_done() {
+            stats.add_block(in_block, encoder.output());
+            try!(out.write_all(encoder.output()));
+            let n = encoder.output().len();
+            encoder.drain(n);
+            in_block = 0;
+        }
+    }
+    stats.hash_collisions = encoder.hash_collisions();
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use compress::{compress_with_options, CompressorOptions, MAX_BLOCK_SIZE};
+    use super::{compress_with_stats, CompressionStats};
+
+    fn compress_both(input: &[u8], options: &CompressorOptions) -> CompressionStats {
+        let mut expected = Vec::new();
+        compress_with_options(&mut Cursor::new(input), &mut expected, options).unwrap();
+        let mut out = Vec::new();
+        let stats = compress_with_stats(&mut Cursor::new(input), &mut out, options).unwrap();
+        assert!(out == expected);
+        stats
+    }
+
+    #[test]
+    fn test_stats_text() {
+        let text = include_bytes!("../tests/moonstone-short.txt");
+        for &block_size in [16, 4096, 65535].iter() {
+            let options = CompressorOptions { block_size: block_size, ..Default::default() };
+            let stats = compress_both(&text[..], &options);
+            assert_eq!(stats.blocks.iter().map(|b| b.0).sum::<usize>(), text.len());
+            assert_eq!(stats.blocks.len(), (text.len() + block_size as usize - 1) / block_size as usize);
+            if block_size == 16 {
+                // Too short for copies
+                assert_eq!(stats.literals, stats.blocks.len() as u64);
+                assert_eq!(stats.literal_bytes, text.len() as u64);
+            } else {
+                assert!(stats.copies_1_byte_offset + stats.copies_2_byte_offset > 0);
+            }
+            assert_eq!(stats.copies_4_byte_offset, 0);
+            assert!(stats.match_lengths.iter().sum::<u64>() <= stats.copies_1_byte_offset + stats.copies_2_byte_offset);
+            assert_eq!(stats.match_lengths[..2].iter().sum::<u64>(), 0);
+        }
+    }
+
+    #[test]
+    fn test_stats_tags() {
+        // A literal, then one long match, emitted as several copy tags
+        let mut input = b"abcdefghijklmnopqrstuvwxyz".to_vec();
+        for _ in 0..3 {
+            input.extend_from_slice(b"abcdefghijklmnopqrstuvwxyz");
+        }
+        let stats = compress_both(&input[..], &Default::default());
+        assert_eq!(stats.literals, 1);
+        assert_eq!(stats.literal_bytes, 26);
+        assert_eq!(stats.copies_1_byte_offset + stats.copies_2_byte_offset, 2);
+        let mut lengths = [0; 16];
+        lengths[6] = 1;
+        assert_eq!(stats.match_lengths, lengths);
+        let mut offsets = [0; 31];
+        offsets[4] = 1;
+        assert_eq!(stats.match_offsets, offsets);
+        assert_eq!(stats.blocks, vec![(104, stats.blocks[0].1)]);
+    }
+
+    #[test]
+    fn test_stats_window() {
+        let part: Vec<u8> = (0..80_000u32).map(|i| (i * 7919 ^ i >> 5) as u8).collect();
+        let input: Vec<u8> = part.iter().cycle().take(200_000).cloned().collect();
+        let options = CompressorOptions { window_size: 100_000, ..Default::default() };
+        let stats = compress_both(&input[..], &options);
+        assert!(stats.copies_4_byte_offset > 0);
+        assert!(stats.match_offsets[16] > 0);
+        assert!(stats.hash_collisions > 0);
+    }
+
+    #[test]
+    fn test_stats_empty() {
+        let stats = compress_both(&[], &Default::default());
+        assert_eq!(stats, CompressionStats { block_size: MAX_BLOCK_SIZE, ..Default::default() });
+    }
+
+    #[test]
+    fn test_stats_block_size() {
+        for &(block_size, expected) in [(0, 16), (15, 16), (16, 16), (4096, 4096)].iter() {
+            let options = CompressorOptions { block_size: block_size, ..Default::default() };
+            let stats = compress_both(&[0; 100][..], &options);
+            assert_eq!(stats.block_size, expected);
+            assert_eq!(stats.blocks.len(), (100 + expected - 1) / expected);
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! The tags of the raw format, for inspecting compressed data and for making it by hand.
+
+use compress::write_varint;
+use decompress::{get_tag_size, read_u32_le, SnappyError};
+use decompress::SnappyError::FormatError;
+
+/// Which of the three copy tags a copy uses, named by the size of its offset.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CopyKind {
+    /// Lengths 4 to 11, offsets up to 2047.
+    OneByteOffset,
+    /// Lengths 1 to 64, offsets up to 65535.
+    TwoByteOffset,
+    /// Lengths 1 to 64, any offset.
+    FourByteOffset,
+}
+
+/// One element of a raw stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tag {
+    /// `len` bytes that are stored as they are, starting at `pos` in the compressed data.
+    Literal { len: u32, pos: usize },
+    /// `len` bytes repeated from `offset` bytes back in the output.
+    Copy { offset: u32, len: u8, tag_kind: CopyKind },
+}
+
+impl Tag {
+    /// How many bytes of output the tag stands for.
+    pub fn output_len(&self) -> u32 {
+        match *self {
+            Tag::Literal { len, .. } => len,
+            Tag::Copy { len, .. }    => len as u32,
+        }
+    }
+}
+
+/// The tags of a raw stream, from `disassemble`.
+///
+/// Each item is a tag, or an error if the stream is cut off in the middle of a tag. Copies are
+/// not checked against the output, so a copy from before the start of the output is returned
+/// as it is. Iteration ends after the first error.
+pub struct Disassembly<'a> {
+    input: &'a [u8],
+    pos: usize,
+    uncompressed_length: Option<u32>,
+    header_error: bool,
+    done: bool,
+}
+
+/// Splits a raw stream into its tags, for debugging.
+pub fn disassemble<'a>(input: &'a [u8]) -> Disassembly<'a> {
+    let mut d = Disassembly::tags(input);
+    let mut length: u32 = 0;
+    for (i, &c) in input.iter().enumerate().take(5) {
+        length |= ((c & 0x7F) as u32) << (7 * i);
+        if c & 0x80 == 0 {
+            d.uncompressed_length = Some(length);
+            d.pos = i + 1;
+            return d;
+        }
+    }
+    d.header_error = true;
+    d
+}
+
+impl <'a> Disassembly<'a> {
+    /// Disassembles tags without a length in front, like one block of the compressor's output.
+    pub fn tags(input: &'a [u8]) -> Disassembly<'a> {
+        Disassembly {
+            input: input,
+            pos: 0,
+            uncompressed_length: None,
+            header_error: false,
+            done: false,
+        }
+    }
+
+    /// The uncompressed length from the start of the stream, if it could be read.
+    pub fn uncompressed_length(&self) -> Option<u32> {
+        self.uncompressed_length
+    }
+
+    /// Where the next tag starts in the input.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn next_tag(&mut self) -> Result<Tag, SnappyError> {
+        let input = self.input;
+        let c = input[self.pos];
+        let extra = get_tag_size(c) - 1;
+        if input.len() - self.pos <= extra {
+            return Err(FormatError("EOF while reading tag"));
+        }
+        let bytes = &input[self.pos + 1..self.pos + 1 + extra];
+        self.pos += 1 + extra;
+        let tag = match c & 0x03 {
+            0 => {
+                let len = if extra == 0 { (c >> 2) as u32 + 1 } else { read_u32_le(bytes).wrapping_add(1) };
+                if len == 0 || (len as u64) > (input.len() - self.pos) as u64 {
+                    return Err(FormatError("EOF while reading literal"));
+                }
+                let tag = Tag::Literal { len: len, pos: self.pos };
+                self.pos += len as usize;
+                tag
+            },
+            1 => Tag::Copy {
+                offset: (((c >> 5) as u32) << 8) | bytes[0] as u32,
+                len: 4 + ((c >> 2) & 0x07),
+                tag_kind: CopyKind::OneByteOffset,
+            },
+            2 => Tag::Copy { offset: read_u32_le(bytes), len: 1 + (c >> 2), tag_kind: CopyKind::TwoByteOffset },
+            _ => Tag::Copy { offset: read_u32_le(bytes), len: 1 + (c >> 2), tag_kind: CopyKind::FourByteOffset },
+        };
+        Ok(tag)
+    }
+}
+
+impl <'a> Iterator for Disassembly<'a> {
+    type Item = Result<Tag, SnappyError>;
+
+    fn next(&mut self) -> Option<Result<Tag, SnappyError>> {
+        if self.done || self.pos == self.input.len() && !self.header_error {
+            return None;
+        }
+        if self.header_error {
+            self.done = true;
+            return Some(Err(FormatError("unterminated uncompressed length")));
+        }
+        let tag = self.next_tag();
+        self.done = tag.is_err();
+        Some(tag)
+    }
+}
+
+/// Builds a raw stream tag by tag, for tests and for making streams our compressor would not.
+///
+/// Each call adds exactly one tag. Nothing is checked against the output, so copies may reach
+/// before its start and the uncompressed length need not match the tags.
+pub struct StreamBuilder {
+    out: Vec<u8>,
+}
+
+impl StreamBuilder {
+    /// Starts a stream that claims to have `uncompressed_length` bytes of output.
+    pub fn new(uncompressed_length: u32) -> StreamBuilder {
+        let mut out = Vec::new();
+        write_varint(&mut out, uncompressed_length).unwrap();
+        StreamBuilder { out: out }
+    }
+
+    /// Adds a literal, with its length in the tag if it fits.
+    ///
+    /// Panics if `data` is empty.
+    pub fn literal(self, data: &[u8]) -> StreamBuilder {
+        let n = data.len().saturating_sub(1);
+        let len_bytes = if n < 60 { 0 } else { (1..4).find(|&i| n < 1 << (8 * i)).unwrap_or(4) };
+        self.literal_with_len_bytes(data, len_bytes)
+    }
+
+    /// Adds a literal with its length in `len_bytes` bytes after the tag, or in the tag for 0.
+    ///
+    /// Panics if `data` is empty, if `len_bytes` is more than 4, or if the length does not fit.
+    pub fn literal_with_len_bytes(mut self, data: &[u8], len_bytes: usize) -> StreamBuilder {
+        assert!(!data.is_empty() && len_bytes <= 4, "invalid literal");
+        let n = data.len() as u64 - 1;
+        if len_bytes == 0 {
+            assert!(n < 60, "literal too long for the tag");
+            self.out.push((n as u8) << 2);
+        } else {
+            assert!(n < 1 << (8 * len_bytes), "literal too long for {} length bytes", len_bytes);
+            self.out.push(((59 + len_bytes) as u8) << 2);
+            for i in 0..len_bytes {
+                self.out.push((n >> (8 * i)) as u8);
+            }
+        }
+        self.out.extend_from_slice(data);
+        self
+    }
+
+    /// Adds a copy with the smallest tag that holds it.
+    ///
+    /// Panics unless `len` is 1 to 64.
+    pub fn copy(self, offset: u32, len: u8) -> StreamBuilder {
+        let kind = if (4..=11).contains(&len) && offset < 1 << 11 {
+            CopyKind::OneByteOffset
+        } else if offset <= 0xFFFF {
+            CopyKind::TwoByteOffset
+        } else {
+            CopyKind::FourByteOffset
+        };
+        self.copy_with_kind(kind, offset, len)
+    }
+
+    /// Adds a copy with the given tag.
+    ///
+    /// Panics if the tag can not hold the offset and length.
+    pub fn copy_with_kind(mut self, kind: CopyKind, offset: u32, len: u8) -> StreamBuilder {
+        match kind {
+            CopyKind::OneByteOffset => {
+                assert!((4..=11).contains(&len) && offset < 1 << 11, "copy does not fit a 1 byte offset tag");
+                self.out.push(((offset >> 3) & 0xE0) as u8 | (len - 4) << 2 | 0x01);
+                self.out.push(offset as u8);
+            },
+            CopyKind::TwoByteOffset => {
+                assert!((1..=64).contains(&len) && offset <= 0xFFFF, "copy does not fit a 2 byte offset tag");
+                self.out.push((len - 1) << 2 | 0x02);
+                self.out.extend_from_slice(&(offset as u16).to_le_bytes());
+            },
+            CopyKind::FourByteOffset => {
+                assert!((1..=64).contains(&len), "copy does not fit a 4 byte offset tag");
+                self.out.push((len - 1) << 2 | 0x03);
+                self.out.extend_from_slice(&offset.to_le_bytes());
+            },
+        }
+        self
+    }
+
+    /// Adds bytes as they are, for making malformed streams.
+    pub fn bytes(mut self, bytes: &[u8]) -> StreamBuilder {
+        self.out.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use compress::compress;
+    use super::{disassemble, CopyKind, Disassembly, StreamBuilder, Tag};
+
+    #[test]
+    fn test_disassemble() {
+        let input = [13, 0x08, 1, 2, 3, 0x05, 3, 0x26, 3, 0, 0x03, 9, 0, 0, 0];
+        let mut d = disassemble(&input[..]);
+        assert_eq!(d.uncompressed_length(), Some(13));
+        assert_eq!(d.position(), 1);
+        assert_eq!(d.next().unwrap().unwrap(), Tag::Literal { len: 3, pos: 2 });
+        assert_eq!(d.position(), 5);
+        let tags: Vec<Tag> = d.map(|t| t.unwrap()).collect();
+        assert_eq!(tags, vec![
+            Tag::Copy { offset: 3, len: 5, tag_kind: CopyKind::OneByteOffset },
+            Tag::Copy { offset: 3, len: 10, tag_kind: CopyKind::TwoByteOffset },
+            Tag::Copy { offset: 9, len: 1, tag_kind: CopyKind::FourByteOffset },
+        ]);
+    }
+
+    #[test]
+    fn test_disassemble_long_literal() {
+        let mut input = vec![0xAC, 0x02, 61 << 2, 0x2B, 0x01];
+        input.extend((0..300).map(|i| i as u8));
+        let tags: Vec<Tag> = disassemble(&input[..]).map(|t| t.unwrap()).collect();
+        assert_eq!(tags, vec![Tag::Literal { len: 300, pos: 5 }]);
+        assert_eq!(tags[0].output_len(), 300);
+    }
+
+    #[test]
+    fn test_disassemble_errors() {
+        let errors = |input: &[u8]| -> Vec<bool> { disassemble(input).map(|t| t.is_err()).collect() };
+        assert_eq!(errors(&[]), vec![true]);
+        assert_eq!(errors(&[0x80, 0x80]), vec![true]);
+        assert_eq!(errors(&[0]), Vec::<bool>::new());
+        assert_eq!(errors(&[5, 0x08, 1, 2]), vec![true]);
+        assert_eq!(errors(&[5, 0x08, 1, 2, 3, 0x26, 3]), vec![false, true]);
+        assert_eq!(errors(&[5, 0xF0, 0]), vec![true]);
+        // Copies are not checked
+        assert_eq!(errors(&[5, 0x05, 9, 0x08, 1, 2, 3]), vec![false, false]);
+    }
+
+    #[test]
+    fn test_disassemble_compressed() {
+        let text = include_bytes!("../tests/moonstone-short.txt");
+        let mut compressed = Vec::new();
+        compress(&mut Cursor::new(&text[..]), &mut compressed).unwrap();
+        let d = disassemble(&compressed[..]);
+        assert_eq!(d.uncompressed_length(), Some(text.len() as u32));
+        let mut out: Vec<u8> = Vec::new();
+        for tag in d {
+            match tag.unwrap() {
+                Tag::Literal { len, pos } => out.extend_from_slice(&compressed[pos..pos + len as usize]),
+                Tag::Copy { offset, len, .. } => for _ in 0..len {
+                    let c = out[out.len() - offset as usize];
+                    out.push(c);
+                }
+            }
+        }
+        assert!(&out[..] == &text[..]);
+
+        let header_len = disassemble(&compressed[..]).position();
+        assert_eq!(Disassembly::tags(&compressed[header_len..]).count(), disassemble(&compressed[..]).count());
+        assert_eq!(Disassembly::tags(&[0x05]).map(|t| t.is_err()).collect::<Vec<_>>(), vec![true]);
+    }
+
+    #[test]
+    fn test_stream_builder() {
+        let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let stream = StreamBuilder::new(1000)
+            .literal(&data[..60])
+            .literal(&data[..61])
+            .literal(&data[..])
+            .literal_with_len_bytes(&data[..1], 4)
+            .copy(2047, 11)
+            .copy(2048, 11)
+            .copy(5, 12)
+            .copy(70000, 1)
+            .copy_with_kind(CopyKind::FourByteOffset, 1, 64)
+            .bytes(&[0x01])
+            .build();
+        assert_eq!(&stream[..6], &[0xE8, 0x07, 59 << 2, 0, 1, 2]);
+        let tags: Vec<Result<Tag, _>> = disassemble(&stream[..]).collect();
+        let tags: Vec<Tag> = tags[..tags.len() - 1].iter().map(|t| *t.as_ref().unwrap()).collect();
+        assert_eq!(tags, vec![
+            Tag::Literal { len: 60, pos: 3 },
+            Tag::Literal { len: 61, pos: 65 },
+            Tag::Literal { len: 300, pos: 129 },
+            Tag::Literal { len: 1, pos: 434 },
+            Tag::Copy { offset: 2047, len: 11, tag_kind: CopyKind::OneByteOffset },
+            Tag::Copy { offset: 2048, len: 11, tag_kind: CopyKind::TwoByteOffset },
+            Tag::Copy { offset: 5, len: 12, tag_kind: CopyKind::TwoByteOffset },
+            Tag::Copy { offset: 70000, len: 1, tag_kind: CopyKind::FourByteOffset },
+            Tag::Copy { offset: 1, len: 64, tag_kind: CopyKind::FourByteOffset },
+        ]);
+        // The one byte added as it is is the start of a tag that is cut off
+        assert!(disassemble(&stream[..]).last().unwrap().is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_stream_builder_copy_too_long() {
+        StreamBuilder::new(0).copy(1, 65);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_stream_builder_literal_too_long() {
+        StreamBuilder::new(0).literal_with_len_bytes(&[0; 257], 1);
+    }
+}
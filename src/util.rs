@@ -1,3 +1,6 @@
+use std::io::{Read, Write};
+use std::io;
+
 pub fn next_power_of_2(n: u32) -> u32 {
     let mut v = n.wrapping_sub(1);
     v |= v >> 1;
@@ -7,3 +10,48 @@ pub fn next_power_of_2(n: u32) -> u32 {
     v |= v >> 16;
     v.wrapping_add(1)
 }
+
+pub fn write_u32_be<W: Write>(out: &mut W, n: u32) -> io::Result<()> {
+    out.write_all(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8])
+}
+
+/// Fills `buf`, or returns false if the reader is already at EOF.
+pub fn read_exact_or_eof<R: Read>(inp: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match inp.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0)  => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "EOF while reading length")),
+            Ok(n)  => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(true)
+}
+
+/// Reads a big endian u32, or returns None if the reader is already at EOF.
+pub fn read_u32_be_or_eof<R: Read>(inp: &mut R) -> io::Result<Option<u32>> {
+    let mut buf = [0; 4];
+    if !try!(read_exact_or_eof(inp, &mut buf)) {
+        return Ok(None);
+    }
+    Ok(Some(((buf[0] as u32) << 24) | ((buf[1] as u32) << 16) | ((buf[2] as u32) << 8) | buf[3] as u32))
+}
+
+pub fn read_u32_be<R: Read>(inp: &mut R) -> io::Result<u32> {
+    match try!(read_u32_be_or_eof(inp)) {
+        Some(n) => Ok(n),
+        None    => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "EOF while reading length"))
+    }
+}
+
+/// Reads exactly `len` bytes into `buf`, without trusting `len` for the allocation size.
+pub fn read_chunk<R: Read>(inp: &mut R, len: u32, buf: &mut Vec<u8>) -> io::Result<()> {
+    buf.clear();
+    try!(inp.take(len as u64).read_to_end(buf));
+    if buf.len() != len as usize {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "EOF while reading chunk"));
+    }
+    Ok(())
+}
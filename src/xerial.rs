@@ -0,0 +1,212 @@
+//! The stream format of snappy-java's `SnappyOutputStream`, as written by Kafka and Spark.
+//!
+//! A stream starts with a 16 byte header: the magic bytes `\x82SNAPPY\0` followed by
+//! the big endian i32 format version and minimum compatible version. After that follows
+//! a sequence of blocks, each a big endian i32 length followed by that many bytes of raw
+//! Snappy data. Streams may be concatenated, in which case the header is repeated.
+
+use std::io::{Read, Write, Cursor};
+use std::io;
+use std::cmp;
+use compress::compress;
+use decompress::{decompress, max_uncompressed_length, peek_uncompressed_length};
+use util::{write_u32_be, read_u32_be, read_u32_be_or_eof, read_chunk};
+
+pub const XERIAL_MAGIC: [u8; 8] = [0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0];
+
+const XERIAL_VERSION: u32 = 1;
+const XERIAL_COMPATIBLE_VERSION: u32 = 1;
+
+/// The default block size of `SnappyOutputStream`.
+pub const XERIAL_BLOCK_SIZE: usize = 32 * 1024;
+
+/// Compresses everything written to it into the snappy-java stream format.
+///
+/// The header is written on the first write, or when the writer is finished,
+/// so even an empty stream gets one. Any partial block is written by `flush`,
+/// `into_inner` or when the writer is dropped.
+pub struct XerialWriter<W: Write> {
+    inner: Option<W>,
+    block_size: usize,
+    wrote_header: bool,
+    buf: Vec<u8>,
+    compressed: Vec<u8>,
+}
+
+impl <W: Write> XerialWriter<W> {
+    pub fn new(inner: W) -> XerialWriter<W> {
+        XerialWriter::with_block_size(inner, XERIAL_BLOCK_SIZE)
+    }
+
+    pub fn with_block_size(inner: W, block_size: usize) -> XerialWriter<W> {
+        assert!(block_size > 0 && block_size <= ::std::i32::MAX as usize);
+        XerialWriter {
+            inner: Some(inner),
+            block_size: block_size,
+            wrote_header: false,
+            buf: Vec::with_capacity(block_size),
+            compressed: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Writes any buffered data and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        try!(self.write_block());
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.wrote_header {
+            return Ok(());
+        }
+        let inner = self.inner.as_mut().unwrap();
+        try!(inner.write_all(&XERIAL_MAGIC));
+        try!(write_u32_be(inner, XERIAL_VERSION));
+        try!(write_u32_be(inner, XERIAL_COMPATIBLE_VERSION));
+        self.wrote_header = true;
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        try!(self.write_header());
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.compressed.clear();
+        try!(compress(&mut Cursor::new(&self.buf[..]), &mut self.compressed));
+        let inner = self.inner.as_mut().unwrap();
+        try!(write_u32_be(inner, self.compressed.len() as u32));
+        try!(inner.write_all(&self.compressed[..]));
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl <W: Write> Write for XerialWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        try!(self.write_header());
+        let n = cmp::min(data.len(), self.block_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == self.block_size {
+            try!(self.write_block());
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.write_block());
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl <W: Write> Drop for XerialWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_block();
+        }
+    }
+}
+
+/// Reads the rest of a stream header, given its first four bytes, and checks the magic and
+/// the version.
+pub fn read_rest_of_header<R: Read>(inp: &mut R, start: u32) -> io::Result<()> {
+    let mut header = [0; 8];
+    header[0] = (start >> 24) as u8;
+    header[1] = (start >> 16) as u8;
+    header[2] = (start >> 8) as u8;
+    header[3] = start as u8;
+    try!(inp.read_exact(&mut header[4..]));
+    if header != XERIAL_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid snappy-java header"));
+    }
+    let _version = try!(read_u32_be(inp));
+    let compatible_version = try!(read_u32_be(inp));
+    if compatible_version > XERIAL_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported snappy-java version"));
+    }
+    Ok(())
+}
+
+/// Decompresses a snappy-java stream, including concatenated streams.
+pub struct XerialReader<R: Read> {
+    inner: R,
+    header_seen: bool,
+    block: Vec<u8>,
+    pos: usize,
+    chunk: Vec<u8>,
+}
+
+impl <R: Read> XerialReader<R> {
+    pub fn new(inner: R) -> XerialReader<R> {
+        XerialReader {
+            inner: inner,
+            header_seen: false,
+            block: Vec::new(),
+            pos: 0,
+            chunk: Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the rest of a header, given the first four bytes of it.
+    fn read_header(&mut self, start: u32) -> io::Result<()> {
+        try!(read_rest_of_header(&mut self.inner, start));
+        self.header_seen = true;
+        Ok(())
+    }
+
+    /// Reads and decompresses the next block. Returns false on EOF.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let mut len = match try!(read_u32_be_or_eof(&mut self.inner)) {
+            None    => {
+                if !self.header_seen {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "missing snappy-java header"));
+                }
+                return Ok(false);
+            },
+            Some(n) => n
+        };
+        // Block lengths are non-negative i32s, so a length starting with the first magic byte
+        // can only be the header of the first stream or of a concatenated one.
+        while !self.header_seen || (len >> 24) as u8 == XERIAL_MAGIC[0] {
+            try!(self.read_header(len));
+            len = match try!(read_u32_be_or_eof(&mut self.inner)) {
+                None    => return Ok(false),
+                Some(n) => n
+            };
+        }
+        if len > ::std::i32::MAX as u32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "negative snappy-java block length"));
+        }
+        try!(read_chunk(&mut self.inner, len, &mut self.chunk));
+        // Checked before decompressing, since the length has not been checked yet
+        if try!(peek_uncompressed_length(&self.chunk[..])) as usize > max_uncompressed_length(self.chunk.len()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "uncompressed length too large for block"));
+        }
+        self.block.clear();
+        self.pos = 0;
+        try!(decompress(&mut Cursor::new(&self.chunk[..]), &mut self.block));
+        Ok(true)
+    }
+}
+
+impl <R: Read> Read for XerialReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.block.len() {
+            if !try!(self.read_block()) {
+                return Ok(0);
+            }
+        }
+        let n = cmp::min(buf.len(), self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
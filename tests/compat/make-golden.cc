@@ -0,0 +1,67 @@
+// Compresses stdin to stdout with the reference C++ Snappy, for the golden files in this directory.
+//
+//     make-golden raw     writes a raw block, as snappy::Compress makes it
+//     make-golden framed  writes the framing format, with each chunk made by snappy::Compress
+//
+// The library has no framing writer of its own, so the few lines of framing are done here. Chunks
+// are always stored compressed, so that every byte of the output comes from snappy::Compress.
+
+#include <snappy.h>
+
+#include <cstdint>
+#include <cstdio>
+#include <cstring>
+#include <iostream>
+#include <iterator>
+#include <string>
+
+static uint32_t crc32c(const char* data, size_t len) {
+    uint32_t crc = 0xFFFFFFFF;
+    for (size_t i = 0; i < len; i++) {
+        crc ^= static_cast<uint8_t>(data[i]);
+        for (int bit = 0; bit < 8; bit++) {
+            crc = (crc >> 1) ^ (0x82F63B78 & (0 - (crc & 1)));
+        }
+    }
+    return ~crc;
+}
+
+static uint32_t masked_crc32c(const char* data, size_t len) {
+    uint32_t crc = crc32c(data, len);
+    return ((crc >> 15) | (crc << 17)) + 0xA282EAD8;
+}
+
+static void put_chunk_header(std::string* out, uint8_t type, uint32_t len, uint32_t crc) {
+    const char header[8] = {
+        static_cast<char>(type), static_cast<char>(len), static_cast<char>(len >> 8),
+        static_cast<char>(len >> 16), static_cast<char>(crc), static_cast<char>(crc >> 8),
+        static_cast<char>(crc >> 16), static_cast<char>(crc >> 24),
+    };
+    out->append(header, sizeof(header));
+}
+
+int main(int argc, char** argv) {
+    if (argc != 2 || (std::strcmp(argv[1], "raw") != 0 && std::strcmp(argv[1], "framed") != 0)) {
+        std::cerr << "usage: make-golden raw|framed < input > output" << std::endl;
+        return 2;
+    }
+    std::string input((std::istreambuf_iterator<char>(std::cin)), std::istreambuf_iterator<char>());
+    std::string output;
+    if (std::strcmp(argv[1], "raw") == 0) {
+        snappy::Compress(input.data(), input.size(), &output);
+    } else {
+        static const size_t kMaxChunk = 65536;
+        output.append("\xff\x06\x00\x00sNaPpY", 10);
+        for (size_t pos = 0; pos < input.size(); pos += kMaxChunk) {
+            size_t n = input.size() - pos < kMaxChunk ? input.size() - pos : kMaxChunk;
+            std::string chunk;
+            snappy::Compress(input.data() + pos, n, &chunk);
+            put_chunk_header(&output, 0x00, static_cast<uint32_t>(chunk.size() + 4),
+                         masked_crc32c(input.data() + pos, n));
+            output += chunk;
+        }
+    }
+    std::cout.write(output.data(), output.size());
+    std::cout << std::flush;
+    return std::cout ? 0 : 1;
+}
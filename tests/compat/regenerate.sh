@@ -0,0 +1,17 @@
+#!/bin/sh
+# Remakes the golden files in this directory from the files in benches/data, with the reference
+# C++ Snappy. Needs a C++ compiler and the library with its headers (e.g. libsnappy-dev).
+# Afterwards, update the version in the header of tests/test-compat.rs.
+set -e
+
+compat_dir=$(cd "$(dirname "$0")" && pwd)
+root=$(dirname "$(dirname "$compat_dir")")
+data="$root/benches/data"
+make_golden="${TMPDIR:-/tmp}/make-golden"
+
+c++ -O2 -o "$make_golden" "$compat_dir/make-golden.cc" -lsnappy
+
+for name in alice29.txt fireworks.jpeg geo.protodata html kppkn.gtb; do
+    "$make_golden" raw < "$data/$name" > "$compat_dir/$name.rawsnappy"
+done
+"$make_golden" framed < "$data/alice29.txt" > "$compat_dir/alice29.txt.sz"
@@ -0,0 +1,137 @@
+extern crate rsnappy;
+
+use std::io::{Read, Write, Cursor};
+use rsnappy::{compress, decompress_auto, detect_format, stream_info, AutoDecoder, Format, FramedWriter,
+              HadoopWriter, StreamInfo, XerialWriter};
+
+static TEXT: &'static str = include_str!("moonstone-short.txt");
+
+fn raw(inp: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    compress(&mut Cursor::new(inp), &mut out).unwrap();
+    out
+}
+
+fn hadoop(inp: &[u8]) -> Vec<u8> {
+    let mut writer = HadoopWriter::new(Vec::new());
+    writer.write_all(inp).unwrap();
+    writer.into_inner().unwrap()
+}
+
+fn xerial(inp: &[u8]) -> Vec<u8> {
+    let mut writer = XerialWriter::new(Vec::new());
+    writer.write_all(inp).unwrap();
+    writer.into_inner().unwrap()
+}
+
+fn framed_hello() -> Vec<u8> {
+    vec![0xFF, 6, 0, 0, b's', b'N', b'a', b'P', b'p', b'Y',
+         0x01 /* uncompressed data */, 9, 0, 0,
+         187, 31, 28, 25 /* masked CRC-32C */, b'h', b'e', b'l', b'l', b'o']
+}
+
+fn check_auto(compressed: &[u8], expected: &[u8], format: Format) {
+    assert_eq!(detect_format(compressed), format);
+    let (out, detected) = decompress_auto(compressed).unwrap();
+    assert_eq!(detected, format);
+    assert!(out == expected);
+
+    let mut decoder = AutoDecoder::new(Cursor::new(compressed)).unwrap();
+    assert_eq!(decoder.format(), format);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == expected);
+}
+
+#[test]
+fn test_auto_raw() {
+    check_auto(&raw(TEXT.as_bytes()), TEXT.as_bytes(), Format::Raw);
+    check_auto(&raw(b"hello"), b"hello", Format::Raw);
+    check_auto(&raw(b""), b"", Format::Raw);
+}
+
+#[test]
+fn test_auto_framed() {
+    assert_eq!(framed(b"hello"), framed_hello());
+    check_auto(&framed_hello(), b"hello", Format::Framed);
+}
+
+#[test]
+fn test_auto_framed_bad_checksum() {
+    let mut input = framed_hello();
+    input[14] ^= 1;
+    assert!(decompress_auto(&input).is_err());
+}
+
+#[test]
+fn test_auto_framed_length_too_large() {
+    let mut input = framed_hello();
+    input.truncate(10);
+    input.extend_from_slice(&[0x00 /* compressed data */, 9, 0, 0, 0, 0, 0, 0,
+                              0xFF, 0xFF, 0xFF, 0xFF, 0x0F /* 4 GiB uncompressed length */]);
+    assert!(decompress_auto(&input).is_err());
+}
+
+#[test]
+fn test_auto_hadoop() {
+    check_auto(&hadoop(TEXT.as_bytes()), TEXT.as_bytes(), Format::Hadoop);
+    check_auto(&hadoop(b"hello"), b"hello", Format::Hadoop);
+}
+
+#[test]
+fn test_auto_xerial() {
+    check_auto(&xerial(TEXT.as_bytes()), TEXT.as_bytes(), Format::Xerial);
+    check_auto(&xerial(b""), b"", Format::Xerial);
+}
+
+fn framed(inp: &[u8]) -> Vec<u8> {
+    let mut writer = FramedWriter::new(Vec::new());
+    writer.write_all(inp).unwrap();
+    writer.into_inner().unwrap()
+}
+
+#[test]
+fn test_auto_framed_roundtrip() {
+    let random: Vec<u8> = (0..1000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+    check_auto(&framed(TEXT.as_bytes()), TEXT.as_bytes(), Format::Framed);
+    check_auto(&framed(&random), &random, Format::Framed);
+    check_auto(&framed(b""), b"", Format::Framed);
+}
+
+#[test]
+fn test_stream_info() {
+    let inp = TEXT.as_bytes();
+    let n = inp.len() as u64;
+    for &(ref compressed, format, chunks) in [(raw(inp), Format::Raw, 1),
+                                              (framed(inp), Format::Framed, (n + 65535) / 65536),
+                                              (hadoop(inp), Format::Hadoop, 1),
+                                              (xerial(inp), Format::Xerial, (n + 32767) / 32768)].iter() {
+        let info = stream_info(Cursor::new(&compressed[..])).unwrap();
+        assert_eq!(info, StreamInfo {
+            format: format,
+            compressed_len: compressed.len() as u64,
+            uncompressed_len: n,
+            chunks: chunks,
+        });
+    }
+}
+
+#[test]
+fn test_stream_info_truncated() {
+    let compressed = framed(TEXT.as_bytes());
+    assert!(stream_info(Cursor::new(&compressed[..compressed.len() - 1])).is_err());
+}
+
+#[test]
+fn test_stream_info_bad_xerial_header() {
+    // The header of a concatenated stream is only recognized by its first byte.
+    let first = xerial(TEXT.as_bytes());
+    let mut compressed = first.clone();
+    compressed.extend_from_slice(&first[..]);
+    compressed[first.len() + 5] ^= 0x20;
+    assert!(stream_info(Cursor::new(&compressed[..])).is_err());
+    let mut compressed = first.clone();
+    compressed.extend_from_slice(&first[..]);
+    compressed[first.len() + 15] = 2;
+    assert!(stream_info(Cursor::new(&compressed[..])).is_err());
+}
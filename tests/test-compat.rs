@@ -0,0 +1,162 @@
+//! Compatibility with other Snappy implementations.
+//!
+//! The files in `compat/` were compressed by an independent implementation: `*.rawsnappy` are raw
+//! blocks and `*.sz` is the framing format, made from the files of the same name in `benches/data`.
+//! They contain tag sequences our own compressor does not produce.
+//!
+//! `compat/regenerate.sh` makes them with the reference C++ Snappy, through the small driver in
+//! `compat/make-golden.cc`, and is the intended source of these files. The files checked in now
+//! were not made that way: they come from the `snap` crate (version 1.1.1), a Rust port of the
+//! reference encoder, with `snap::raw::Encoder` and `snap::write::FrameEncoder`. When they are
+//! regenerated, record the Snappy version and the command here.
+//!
+//! In the other direction, our compressed output is decoded by `reference_decompress` below,
+//! a deliberately simple decoder written directly from the format description, so that a bug
+//! shared by our compressor and decompressor can not go unnoticed.
+
+extern crate rsnappy;
+
+use std::io::{Cursor, Read};
+use rsnappy::{compress, compress_with_options, decompress, AutoDecoder, CompressorOptions, Format, FramedReader};
+
+static TEXT: &'static str = include_str!("moonstone-short.txt");
+
+static GOLDEN: &'static [(&'static str, &'static [u8], &'static [u8])] = &[
+    ("alice29.txt", include_bytes!("../benches/data/alice29.txt"), include_bytes!("compat/alice29.txt.rawsnappy")),
+    ("fireworks.jpeg", include_bytes!("../benches/data/fireworks.jpeg"), include_bytes!("compat/fireworks.jpeg.rawsnappy")),
+    ("geo.protodata", include_bytes!("../benches/data/geo.protodata"), include_bytes!("compat/geo.protodata.rawsnappy")),
+    ("html", include_bytes!("../benches/data/html"), include_bytes!("compat/html.rawsnappy")),
+    ("kppkn.gtb", include_bytes!("../benches/data/kppkn.gtb"), include_bytes!("compat/kppkn.gtb.rawsnappy")),
+];
+
+static GOLDEN_FRAMED: &'static [u8] = include_bytes!("compat/alice29.txt.sz");
+
+/// Decodes a raw Snappy block, following the format description as literally as possible.
+fn reference_decompress(input: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut pos = 0;
+    let mut next = || -> Result<u8, &'static str> {
+        let b = try!(input.get(pos).cloned().ok_or("unexpected end of input"));
+        pos += 1;
+        Ok(b)
+    };
+
+    // The preamble is the uncompressed length as a little endian varint.
+    let mut len: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let b = try!(next());
+        len |= ((b & 0x7F) as u64) << shift;
+        if b & 0x80 == 0 { break; }
+        shift += 7;
+        if shift > 28 { return Err("varint too long"); }
+    }
+
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let tag = match next() {
+            Ok(tag) => tag,
+            Err(_)  => break
+        };
+        match tag & 0x03 {
+            0 => {
+                // Literal. Lengths 1-60 are stored in the tag, longer ones in 1-4 following bytes.
+                let mut n = (tag >> 2) as u64;
+                if n >= 60 {
+                    let bytes = n - 59;
+                    n = 0;
+                    for i in 0..bytes {
+                        n |= (try!(next()) as u64) << (8 * i);
+                    }
+                }
+                for _ in 0..n + 1 {
+                    out.push(try!(next()));
+                }
+            },
+            kind => {
+                let (len, offset) = match kind {
+                    1 => (4 + ((tag >> 2) & 0x07) as usize, (((tag >> 5) as usize) << 8) | try!(next()) as usize),
+                    2 => (1 + (tag >> 2) as usize, try!(next()) as usize | (try!(next()) as usize) << 8),
+                    _ => {
+                        let mut offset = 0;
+                        for i in 0..4 {
+                            offset |= (try!(next()) as usize) << (8 * i);
+                        }
+                        (1 + (tag >> 2) as usize, offset)
+                    }
+                };
+                if offset == 0 || offset > out.len() {
+                    return Err("invalid copy offset");
+                }
+                // Byte by byte, since the copy may overlap its own output.
+                for _ in 0..len {
+                    let b = out[out.len() - offset];
+                    out.push(b);
+                }
+            }
+        }
+    }
+    if out.len() as u64 != len {
+        return Err("length does not match preamble");
+    }
+    Ok(out)
+}
+
+#[test]
+fn test_reference_decompress_golden() {
+    // Make sure the reference decoder itself agrees with the other implementation.
+    for &(name, original, compressed) in GOLDEN.iter() {
+        let out = reference_decompress(compressed).unwrap();
+        assert!(&out[..] == original, "{}", name);
+    }
+}
+
+#[test]
+fn test_decompress_golden() {
+    for &(name, original, compressed) in GOLDEN.iter() {
+        let mut out = Vec::new();
+        decompress(&mut Cursor::new(compressed), &mut out).unwrap();
+        assert!(&out[..] == original, "{}", name);
+    }
+}
+
+#[test]
+fn test_decompress_golden_framed() {
+    let mut out = Vec::new();
+    FramedReader::new(GOLDEN_FRAMED).read_to_end(&mut out).unwrap();
+    assert!(&out[..] == GOLDEN[0].1);
+
+    let mut decoder = AutoDecoder::new(GOLDEN_FRAMED).unwrap();
+    assert_eq!(decoder.format(), Format::Framed);
+    out.clear();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(&out[..] == GOLDEN[0].1);
+}
+
+#[test]
+fn test_compress_reference_decompress() {
+    let mut inputs: Vec<&[u8]> = GOLDEN.iter().map(|g| g.1).collect();
+    inputs.push(TEXT.as_bytes());
+    inputs.push(&[]);
+    inputs.push(&[0; 100_000]);
+    for &input in inputs.iter() {
+        for &block_size in [16, 1000, 4096, 65535].iter() {
+            let mut compressed = Vec::new();
+            let options = CompressorOptions { block_size: block_size, ..Default::default() };
+            compress_with_options(&mut Cursor::new(input), &mut compressed, &options).unwrap();
+            let out = reference_decompress(&compressed[..]).unwrap();
+            assert!(&out[..] == input, "input of length {}, block size {}", input.len(), block_size);
+        }
+    }
+}
+
+#[test]
+fn test_compress_size_close_to_golden() {
+    // Within 5% of the golden size. Not a requirement of the format, but a regression here
+    // would likely be a compressor bug.
+    for &(name, original, golden) in GOLDEN.iter() {
+        let mut compressed = Vec::new();
+        compress(&mut Cursor::new(original), &mut compressed).unwrap();
+        assert!(compressed.len() <= golden.len() + golden.len() / 20,
+                "{}: {} bytes, golden {} bytes", name, compressed.len(), golden.len());
+    }
+}
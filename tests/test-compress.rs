@@ -2,7 +2,7 @@ extern crate rsnappy;
 
 use std::io::Cursor;
 use std::cmp;
-use rsnappy::{compress, decompress};
+use rsnappy::{compress, compress_with_options, decompress, CompressorOptions};
 
 static TEXT: &'static str = include_str!("moonstone-short.txt");
 
@@ -64,3 +64,33 @@ fn test_roundtrip(inp: &[u8]) {
     assert_eq!(&inp[..n], &roundtrip[..n]);  // Hopefully avoid some very long error messages
     assert_eq!(inp, &roundtrip[..]);
 }
+
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// The compressed output must not depend on which implementation is used, so this must pass
+/// both with and without the `safe` feature. The checksums only need updating when the
+/// compressor is deliberately changed.
+#[test]
+fn test_compressed_output_checksums() {
+    let inputs: &[(&str, &[u8])] = &[
+        ("text", TEXT.as_bytes()),
+        ("html", include_bytes!("../benches/data/html")),
+        ("kppkn.gtb", include_bytes!("../benches/data/kppkn.gtb")),
+        ("fireworks.jpeg", include_bytes!("../benches/data/fireworks.jpeg")),
+    ];
+    let expected: &[(u16, [u64; 4])] = &[
+        (16,    [0x34b09fd8989e2cff, 0x5e8f9ea19ee312f1, 0xf5272e0abd3d7076, 0x4c7a5d529d21df1a]),
+        (4096,  [0x32bded3e60731d3a, 0xc08c301920885f17, 0x7efa20ed3d644a59, 0x4bf79abdc51b9acc]),
+        (65535, [0x1920b4180111d5b3, 0x1a9b7f92da189678, 0xadf661dcb0857503, 0x09b18eaf5cc4df3b]),
+    ];
+    for &(block_size, ref checksums) in expected.iter() {
+        for (&(name, input), &checksum) in inputs.iter().zip(checksums.iter()) {
+            let mut out = Vec::new();
+            let options = CompressorOptions { block_size: block_size, ..Default::default() };
+            compress_with_options(&mut Cursor::new(input), &mut out, &options).unwrap();
+            assert!(fnv1a(&out[..]) == checksum, "{} with block size {}", name, block_size);
+        }
+    }
+}
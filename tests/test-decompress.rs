@@ -1,7 +1,9 @@
 extern crate rsnappy;
 
-use std::io::Cursor;
-use rsnappy::{decompress};
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Read};
+use rsnappy::{compress, decompress, CopyKind, RingWriter, StreamBuilder};
 
 macro_rules! decompress(
     ($input: expr, $output: expr) => (
@@ -23,7 +25,7 @@ macro_rules! decompress(
 
 #[test]
 fn test_small_literal() {
-    let input = vec![7u8 /* uncompressed length */, 6 << 2 /* 7-byte literal */, 1, 2, 3, 4, 5, 6, 7];
+    let input = StreamBuilder::new(7).literal(&[1, 2, 3, 4, 5, 6, 7]).build();
     let mut output = vec![];
     decompress!(&input, &mut output);
     assert_eq!(&output[..], &[1, 2, 3, 4, 5, 6, 7]);
@@ -31,9 +33,10 @@ fn test_small_literal() {
 
 #[test]
 fn test_two_small_literals() {
-    let input = vec![7u8 /* uncompressed length */,
-        2 << 2 /* 3-byte literal */, 1, 2, 3,
-        3 << 2 /* 4-byte literal */, 4, 5, 6, 7];
+    let input = StreamBuilder::new(7)
+        .literal(&[1, 2, 3])
+        .literal(&[4, 5, 6, 7])
+        .build();
     let mut output = vec![];
     decompress!(&input, &mut output);
     assert_eq!(&output[..], &[1, 2, 3, 4, 5, 6, 7]);
@@ -43,10 +46,7 @@ fn test_two_small_literals() {
 fn test_big_literal() {
     let mut expected_out = vec![];
     for i in 0..1_000_000 { expected_out.push((i % 10) as u8); }
-    let mut input = vec![64 | 0x80, 4 | 0x80, 61, /* uncompressed length */
-                        63 << 2 /* long 4-byte literal */,
-                        0x3F, 0x42, 0x0F, 0x00 /* 1_000_000_000 in little endian */];
-    for &i in expected_out.iter() { input.push(i); }
+    let input = StreamBuilder::new(1_000_000).literal_with_len_bytes(&expected_out, 4).build();
     let mut output = vec![];
     decompress!(&input, &mut output);
     assert_eq!(&output[..], &expected_out[..]);
@@ -54,9 +54,10 @@ fn test_big_literal() {
 
 #[test]
 fn test_1byte_copy() {
-    let input = vec![11u8 /* uncompressed length */,
-        5 << 2 /* 6-byte literal */, 1, 2, 3, 4, 5, 6,
-        0b00000101 /* 1-byte offset copy, 5 bytes */, 6 /* 6-byte offset */];
+    let input = StreamBuilder::new(11)
+        .literal(&[1, 2, 3, 4, 5, 6])
+        .copy_with_kind(CopyKind::OneByteOffset, 6, 5)
+        .build();
     let mut output = vec![];
     decompress!(&input, &mut output);
     assert_eq!(&output[..], &[1, 2, 3, 4, 5, 6, 1, 2, 3, 4, 5]);
@@ -64,9 +65,10 @@ fn test_1byte_copy() {
 
 #[test]
 fn test_2byte_copy() {
-    let input = vec![9u8 /* uncompressed length */,
-        5 << 2 /* 6-byte literal */, 1, 2, 3, 4, 5, 6,
-        0b00001010 /* 2-byte offset copy, 3 bytes */, 5, 0 /* 5-byte offset */];
+    let input = StreamBuilder::new(9)
+        .literal(&[1, 2, 3, 4, 5, 6])
+        .copy_with_kind(CopyKind::TwoByteOffset, 5, 3)
+        .build();
     let mut output = vec![];
     decompress!(&input, &mut output);
     assert_eq!(&output[..], &[1, 2, 3, 4, 5, 6, 2, 3, 4]);
@@ -74,9 +76,10 @@ fn test_2byte_copy() {
 
 #[test]
 fn test_4byte_copy() {
-    let input = vec![9u8 /* uncompressed length */,
-        5 << 2 /* 6-byte literal */, 1, 2, 3, 4, 5, 6,
-        0b00001011 /* 5-byte offset copy, 3 bytes */, 5, 0, 0, 0 /* 5-byte offset */];
+    let input = StreamBuilder::new(9)
+        .literal(&[1, 2, 3, 4, 5, 6])
+        .copy_with_kind(CopyKind::FourByteOffset, 5, 3)
+        .build();
     let mut output = vec![];
     decompress!(&input, &mut output);
     assert_eq!(&output[..], &[1, 2, 3, 4, 5, 6, 2, 3, 4]);
@@ -84,9 +87,10 @@ fn test_4byte_copy() {
 
 #[test]
 fn test_repeat_copy() {
-        let input = vec![7u8 /* uncompressed length */,
-        2 << 2 /* 3-byte literal */, 1, 2, 3,
-        0b00000001 /* 1-byte offset copy, 4 bytes */, 2 /* 4-byte offset */];
+    let input = StreamBuilder::new(7)
+        .literal(&[1, 2, 3])
+        .copy_with_kind(CopyKind::OneByteOffset, 2, 4)
+        .build();
     let mut output = vec![];
     decompress!(&input, &mut output);
     assert_eq!(&output[..], &[1, 2, 3, 2, 3, 2, 3]);
@@ -103,3 +107,38 @@ fn test_decompress_malformed() {
     let mut out = Vec::new();
     let _ = decompress(&mut Cursor::new(&input[..]), &mut out);
 }
+
+#[test]
+fn test_decompress_length_too_short() {
+    let input = StreamBuilder::new(8).literal(&[1, 2, 3, 4, 5, 6, 7]).build();
+    let mut out = Vec::new();
+    assert!(decompress(&mut Cursor::new(&input[..]), &mut out).is_err());
+}
+
+#[test]
+fn test_decompress_length_too_long() {
+    let input = StreamBuilder::new(9)
+        .literal(&[1, 2, 3, 4, 5, 6])
+        .copy(6, 5)
+        .build();
+    let mut out = Vec::new();
+    assert!(decompress(&mut Cursor::new(&input[..]), &mut out).is_err());
+}
+
+#[test]
+fn test_decompress_to_file() {
+    let data = include_bytes!("../benches/data/alice29.txt");
+    let mut compressed = Vec::new();
+    compress(&mut Cursor::new(&data[..]), &mut compressed).unwrap();
+
+    let path = env::temp_dir().join(format!("rsnappy-test-{}-alice29.txt", std::process::id()));
+    {
+        let mut out = RingWriter::new(BufWriter::new(File::create(&path).unwrap()));
+        decompress!(&compressed, &mut out);
+        out.into_inner().unwrap().into_inner().unwrap();
+    }
+    let mut out = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut out).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(&out[..] == &data[..]);
+}
@@ -0,0 +1,68 @@
+extern crate rsnappy;
+
+use std::io::{Read, Write, Cursor};
+use rsnappy::{HadoopReader, HadoopWriter};
+
+static TEXT: &'static str = include_str!("moonstone-short.txt");
+
+fn hadoop_roundtrip(inp: &[u8], block_size: usize) -> Vec<u8> {
+    let mut writer = HadoopWriter::with_block_size(Vec::new(), block_size);
+    writer.write_all(inp).unwrap();
+    let compressed = writer.into_inner().unwrap();
+    let mut out = Vec::new();
+    HadoopReader::new(Cursor::new(compressed)).read_to_end(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn test_hadoop_roundtrip() {
+    assert_eq!(&hadoop_roundtrip(TEXT.as_bytes(), 256 * 1024)[..], TEXT.as_bytes());
+}
+
+#[test]
+fn test_hadoop_roundtrip_many_blocks() {
+    assert_eq!(&hadoop_roundtrip(TEXT.as_bytes(), 1000)[..], TEXT.as_bytes());
+}
+
+#[test]
+fn test_hadoop_empty() {
+    let compressed = HadoopWriter::new(Vec::new()).into_inner().unwrap();
+    assert!(compressed.is_empty());
+    assert!(hadoop_roundtrip(&[], 1000).is_empty());
+}
+
+#[test]
+fn test_hadoop_block_layout() {
+    let mut writer = HadoopWriter::new(Vec::new());
+    writer.write_all(&[1, 2, 3]).unwrap();
+    let compressed = writer.into_inner().unwrap();
+    assert_eq!(&compressed[..], &[0, 0, 0, 3 /* block length */,
+                                  0, 0, 0, 5 /* chunk length */,
+                                  3 /* uncompressed length */, 2 << 2 /* 3-byte literal */, 1, 2, 3]);
+}
+
+#[test]
+fn test_hadoop_multiple_chunks() {
+    // Hadoop may split one block into several independently compressed chunks.
+    let input = vec![0, 0, 0, 5 /* block length */,
+        0, 0, 0, 4, 2, 1 << 2, 1, 2,
+        0, 0, 0, 5, 3, 2 << 2, 3, 4, 5];
+    let mut out = Vec::new();
+    HadoopReader::new(Cursor::new(input)).read_to_end(&mut out).unwrap();
+    assert_eq!(&out[..], &[1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_hadoop_truncated() {
+    let input = vec![0, 0, 0, 5, 0, 0, 0, 4, 2, 1 << 2, 1];
+    let mut out = Vec::new();
+    assert!(HadoopReader::new(Cursor::new(input)).read_to_end(&mut out).is_err());
+}
+
+#[test]
+fn test_hadoop_chunk_longer_than_block() {
+    let input = vec![0, 0, 0, 5 /* block length */,
+        0, 0, 0, 6, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F /* 4 GiB uncompressed length */, 0];
+    let mut out = Vec::new();
+    assert!(HadoopReader::new(Cursor::new(input)).read_to_end(&mut out).is_err());
+}
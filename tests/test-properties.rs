@@ -0,0 +1,92 @@
+//! Property based tests of compression with arbitrary inputs and options.
+
+extern crate rsnappy;
+extern crate proptest;
+
+use std::io::Cursor;
+use proptest::prelude::*;
+use proptest::collection::vec;
+use rsnappy::{compress_with_options, decompress, max_compressed_length, CompressorOptions, MAX_WINDOW_SIZE};
+
+fn check_roundtrip(input: &[u8], block_size: u16, window_size: u32) -> Result<(), TestCaseError> {
+    let mut compressed = Vec::new();
+    let options = CompressorOptions { block_size: block_size, window_size: window_size };
+    compress_with_options(&mut Cursor::new(input), &mut compressed, &options).unwrap();
+    prop_assert!(compressed.len() <= max_compressed_length(input.len()),
+                 "{} bytes compressed to {}", input.len(), compressed.len());
+    let mut out = Vec::new();
+    decompress(&mut Cursor::new(&compressed[..]), &mut out).unwrap();
+    prop_assert!(out == input, "roundtrip mismatch for {} bytes", input.len());
+    Ok(())
+}
+
+/// Block sizes, weighted towards the small ones. Sizes below 16 (`BLOCK_MARGIN`) are rounded up
+/// to 16 by the compressor, so they test that rounding rather than blocks that short; those are
+/// tested on `compress_block` directly.
+fn block_size() -> BoxedStrategy<u16> {
+    prop_oneof![
+        0u16..40,
+        40u16..4096,
+        any::<u16>(),
+        Just(::std::u16::MAX),
+    ].boxed()
+}
+
+/// Window sizes, mostly none, as that is the default.
+fn window_size() -> BoxedStrategy<u32> {
+    prop_oneof![
+        3 => Just(0),
+        1 => 1u32..200_000,
+        1 => Just(MAX_WINDOW_SIZE),
+    ].boxed()
+}
+
+/// Inputs made of a short pattern repeated many times, with a few bytes changed.
+fn repetitive() -> BoxedStrategy<Vec<u8>> {
+    (vec(any::<u8>(), 1..70), 1usize..3000, vec((any::<usize>(), any::<u8>()), 0..10)).prop_map(|(pattern, n, changes)| {
+        let mut data: Vec<u8> = pattern.iter().cloned().cycle().take(pattern.len() * n).collect();
+        for (i, b) in changes {
+            let len = data.len();
+            data[i % len] = b;
+        }
+        data
+    }).boxed()
+}
+
+/// Inputs made of runs of single bytes, which give long and overlapping copies.
+fn runs() -> BoxedStrategy<Vec<u8>> {
+    vec((any::<u8>(), 1usize..500), 0..100).prop_map(|runs| {
+        runs.iter().flat_map(|&(b, n)| ::std::iter::repeat(b).take(n)).collect()
+    }).boxed()
+}
+
+proptest! {
+    #[test]
+    fn roundtrip_arbitrary(ref input in vec(any::<u8>(), 0..20000), block_size in block_size(), window_size in window_size()) {
+        try!(check_roundtrip(input, block_size, window_size));
+    }
+
+    #[test]
+    fn roundtrip_small_alphabet(ref input in vec(0u8..4, 0..20000), block_size in block_size(), window_size in window_size()) {
+        try!(check_roundtrip(input, block_size, window_size));
+    }
+
+    #[test]
+    fn roundtrip_repetitive(ref input in repetitive(), block_size in block_size(), window_size in window_size()) {
+        try!(check_roundtrip(input, block_size, window_size));
+    }
+
+    #[test]
+    fn roundtrip_runs(ref input in runs(), block_size in block_size(), window_size in window_size()) {
+        try!(check_roundtrip(input, block_size, window_size));
+    }
+}
+
+#[test]
+fn test_max_compressed_length_tiny_blocks() {
+    // Every block costs a tag byte, which would break the bound without a minimum block size.
+    let input: Vec<u8> = (0..10000u32).map(|i| i.wrapping_mul(2654435761) as u8).collect();
+    for block_size in 0..20 {
+        check_roundtrip(&input[..], block_size, 0).unwrap();
+    }
+}
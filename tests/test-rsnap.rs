@@ -0,0 +1,302 @@
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+static TEXT: &'static str = include_str!("moonstone-short.txt");
+
+/// A fresh directory that is removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("rsnap-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn rsnap(dir: &TempDir, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rsnap"))
+        .args(args)
+        .current_dir(&dir.0)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn write_file(path: &Path, data: &[u8]) {
+    File::create(path).unwrap().write_all(data).unwrap();
+}
+
+fn read_file(path: &Path) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn test_compress_decompress_file() {
+    let dir = TempDir::new("file");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+
+    let out = rsnap(&dir, &["text"], b"");
+    assert!(out.status.success());
+    assert!(!dir.path("text").exists());
+    let compressed = read_file(&dir.path("text.sz"));
+    assert!(compressed.starts_with(b"\xFF\x06\x00\x00sNaPpY"));
+    assert!(compressed.len() < TEXT.len());
+
+    let out = rsnap(&dir, &["-d", "text.sz"], b"");
+    assert!(out.status.success());
+    assert!(!dir.path("text.sz").exists());
+    assert_eq!(read_file(&dir.path("text")), TEXT.as_bytes());
+}
+
+#[test]
+fn test_keep() {
+    let dir = TempDir::new("keep");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    assert!(rsnap(&dir, &["-k", "text"], b"").status.success());
+    assert!(dir.path("text").exists());
+    assert!(dir.path("text.sz").exists());
+}
+
+#[test]
+fn test_stdin_stdout() {
+    let dir = TempDir::new("stdin");
+    let compressed = rsnap(&dir, &[], TEXT.as_bytes());
+    assert!(compressed.status.success());
+    let decompressed = rsnap(&dir, &["-d"], &compressed.stdout);
+    assert!(decompressed.status.success());
+    assert_eq!(&decompressed.stdout[..], TEXT.as_bytes());
+}
+
+#[test]
+fn test_stdout_keeps_input() {
+    let dir = TempDir::new("stdout");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    let out = rsnap(&dir, &["-c", "text"], b"");
+    assert!(out.status.success());
+    assert!(dir.path("text").exists());
+    assert!(!dir.path("text.sz").exists());
+    let decompressed = rsnap(&dir, &["-dc", "-"], &out.stdout);
+    assert_eq!(&decompressed.stdout[..], TEXT.as_bytes());
+}
+
+#[test]
+fn test_formats() {
+    let dir = TempDir::new("formats");
+    for format in ["framed", "raw", "hadoop", "xerial"].iter() {
+        let compressed = rsnap(&dir, &["--format", format], TEXT.as_bytes());
+        assert!(compressed.status.success(), "{}", format);
+        let decompressed = rsnap(&dir, &["-d"], &compressed.stdout);
+        assert!(decompressed.status.success(), "{}", format);
+        assert!(&decompressed.stdout[..] == TEXT.as_bytes(), "{}", format);
+    }
+    let compressed = rsnap(&dir, &["-Fraw", "-b", "4"], TEXT.as_bytes());
+    assert!(compressed.status.success());
+}
+
+#[test]
+fn test_existing_output() {
+    let dir = TempDir::new("existing");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    write_file(&dir.path("text.sz"), b"old");
+    let out = rsnap(&dir, &["text"], b"");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("already exists"));
+    assert_eq!(read_file(&dir.path("text.sz")), b"old");
+    assert!(dir.path("text").exists());
+
+    assert!(rsnap(&dir, &["-f", "text"], b"").status.success());
+    assert!(read_file(&dir.path("text.sz")) != b"old");
+}
+
+#[test]
+fn test_errors() {
+    let dir = TempDir::new("errors");
+    write_file(&dir.path("garbage.sz"), b"\xFF\x06\x00\x00sNaPpY\x01\x09\x00\x00xxxxhello");
+    let out = rsnap(&dir, &["-d", "garbage.sz"], b"");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("garbage.sz: checksum mismatch"));
+    assert!(dir.path("garbage.sz").exists());
+    assert!(!dir.path("garbage").exists());
+
+    let out = rsnap(&dir, &["missing"], b"");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("rsnap: missing: "));
+
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    let out = rsnap(&dir, &["-d", "text"], b"");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unknown suffix"));
+
+    assert_eq!(rsnap(&dir, &["--bogus"], b"").status.code(), Some(2));
+    assert_eq!(rsnap(&dir, &["-b", "1000"], b"").status.code(), Some(2));
+}
+
+#[test]
+fn test_test_mode() {
+    let dir = TempDir::new("test-mode");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    assert!(rsnap(&dir, &["text"], b"").status.success());
+    let out = rsnap(&dir, &["-t", "text.sz"], b"");
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+    assert!(dir.path("text.sz").exists());
+    assert!(!dir.path("text").exists());
+
+    // Corrupt a byte of the checksum of the first chunk
+    let mut compressed = read_file(&dir.path("text.sz"));
+    compressed[14] ^= 0x01;
+    write_file(&dir.path("bad.sz"), &compressed);
+    let out = rsnap(&dir, &["--test", "text.sz", "bad.sz"], b"");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("bad.sz: checksum mismatch"));
+
+    // A raw stream whose header claims more data than there is
+    let out = rsnap(&dir, &["-t"], &[8, 6 << 2, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("shorter than uncompressed length"));
+}
+
+#[test]
+fn test_list_mode() {
+    let dir = TempDir::new("list-mode");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    write_file(&dir.path("copy"), TEXT.as_bytes());
+    assert!(rsnap(&dir, &["text"], b"").status.success());
+    assert!(rsnap(&dir, &["-F", "xerial", "copy"], b"").status.success());
+    let compressed_len = read_file(&dir.path("text.sz")).len();
+
+    let out = rsnap(&dir, &["-l", "text.sz", "copy.sz"], b"");
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let lines: Vec<Vec<&str>> = stdout.lines().map(|l| l.split_whitespace().collect()).collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], vec!["compressed", "uncompressed", "ratio", "chunks", "format", "name"]);
+    assert_eq!(lines[1][0], compressed_len.to_string());
+    assert_eq!(lines[1][1], TEXT.len().to_string());
+    assert_eq!(&lines[1][3..], &["2", "framed", "text.sz"]);
+    assert_eq!(&lines[2][4..], &["xerial", "copy.sz"]);
+    assert_eq!(lines[3][1], (2 * TEXT.len()).to_string());
+    assert_eq!(lines[3][5], "(totals)");
+}
+
+#[test]
+fn test_stats() {
+    let dir = TempDir::new("stats");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    let out = rsnap(&dir, &["--stats", "-b", "16", "text"], b"");
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let blocks = (TEXT.len() + 16 * 1024 - 1) / (16 * 1024);
+    assert!(stdout.starts_with(&format!("text: {} -> ", TEXT.len())[..]), "{}", stdout);
+    assert!(stdout.contains(&format!("{} blocks of up to 16384 bytes", blocks)[..]), "{}", stdout);
+    assert!(stdout.contains("match length"));
+    assert!(stdout.contains("copies, 2 byte offset"));
+    // Nothing is written
+    assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+
+    assert_eq!(rsnap(&dir, &["--stats", "missing"], b"").status.code(), Some(1));
+}
+
+#[test]
+fn test_dump() {
+    let dir = TempDir::new("dump");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    assert!(rsnap(&dir, &["-k", "-F", "raw", "text"], b"").status.success());
+
+    let out = rsnap(&dir, &["dump", "text.sz"], b"");
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], format!("uncompressed length {}", TEXT.len()));
+    assert!(lines[2].contains("literal"), "{}", lines[2]);
+    assert!(lines.iter().any(|l| l.contains("copy-2")));
+    let last = lines[lines.len() - 1];
+    assert!(last.ends_with(&format!(" copies, {} bytes of output", TEXT.len())[..]), "{}", last);
+
+    // From standard input, cut off in the middle
+    let compressed = read_file(&dir.path("text.sz"));
+    let out = rsnap(&dir, &["dump"], &compressed[..compressed.len() / 2]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8(out.stdout).unwrap().contains("error: "));
+
+    assert_eq!(rsnap(&dir, &["dump", "missing"], b"").status.code(), Some(1));
+    assert_eq!(rsnap(&dir, &["dump", "a", "b"], b"").status.code(), Some(2));
+}
+
+#[test]
+fn test_bench() {
+    let dir = TempDir::new("bench");
+    write_file(&dir.path("text"), TEXT.as_bytes());
+    let out = rsnap(&dir, &["bench", "-T", "0", "-b", "8,64", "text"], b"");
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let lines: Vec<Vec<&str>> = stdout.lines().map(|l| l.split_whitespace().collect()).collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(&lines[1][..3], &["text", "8K", &TEXT.len().to_string()[..]]);
+    assert_eq!(&lines[2][..3], &["text", "64K", &TEXT.len().to_string()[..]]);
+    assert_eq!(lines[2][lines[2].len() - 1], "MB/s");
+
+    assert_eq!(rsnap(&dir, &["bench", "missing"], b"").status.code(), Some(1));
+    assert_eq!(rsnap(&dir, &["bench", "-b", "0", "text"], b"").status.code(), Some(2));
+}
+
+#[test]
+fn test_dict() {
+    let dir = TempDir::new("dict");
+    let mut samples = Vec::new();
+    for i in 0..50 {
+        let name = format!("msg{}", i);
+        let message = format!("{{\"method\": \"get_user\", \"params\": {{\"id\": {}, \"fields\": [\"name\", \"email\"]}}}}", i * 37);
+        write_file(&dir.path(&name), message.as_bytes());
+        samples.push(name);
+    }
+    let mut args = vec!["dict", "-s", "500", "-o", "dictionary"];
+    args.extend(samples.iter().map(|s| &s[..]));
+    let out = rsnap(&dir, &args[..], b"");
+    assert!(out.status.success());
+    let dictionary = read_file(&dir.path("dictionary"));
+    assert!(dictionary.len() > 0 && dictionary.len() <= 500);
+
+    let sizes = |stdout: Vec<u8>| -> Vec<usize> {
+        String::from_utf8(stdout).unwrap().lines().map(|l| {
+            l.split_whitespace().find(|w| w.parse::<usize>().is_ok()).unwrap().parse().unwrap()
+        }).collect()
+    };
+    let trained = sizes(out.stdout);
+    assert_eq!(trained[1], dictionary.len());
+    assert!(trained[3] < trained[2]);
+
+    // Measuring the written dictionary gives the same numbers
+    let mut args = vec!["dict", "-D", "dictionary"];
+    args.extend(samples.iter().map(|s| &s[..]));
+    let out = rsnap(&dir, &args[..], b"");
+    assert!(out.status.success());
+    assert_eq!(sizes(out.stdout), trained);
+
+    assert_eq!(rsnap(&dir, &["dict", "missing"], b"").status.code(), Some(1));
+    assert_eq!(rsnap(&dir, &["dict", "-s", "40000", "msg0"], b"").status.code(), Some(2));
+    assert_eq!(rsnap(&dir, &["dict", "-D", "dictionary", "-o", "x", "msg0"], b"").status.code(), Some(2));
+    assert_eq!(rsnap(&dir, &["dict"], b"").status.code(), Some(2));
+}
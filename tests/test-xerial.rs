@@ -0,0 +1,73 @@
+extern crate rsnappy;
+
+use std::io::{Read, Write, Cursor};
+use rsnappy::{XerialReader, XerialWriter};
+
+static TEXT: &'static str = include_str!("moonstone-short.txt");
+
+const HEADER: [u8; 16] = [0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0, 0, 0, 0, 1, 0, 0, 0, 1];
+
+fn xerial_decompress(input: Vec<u8>) -> Vec<u8> {
+    let mut out = Vec::new();
+    XerialReader::new(Cursor::new(input)).read_to_end(&mut out).unwrap();
+    out
+}
+
+fn xerial_roundtrip(inp: &[u8], block_size: usize) -> Vec<u8> {
+    let mut writer = XerialWriter::with_block_size(Vec::new(), block_size);
+    writer.write_all(inp).unwrap();
+    xerial_decompress(writer.into_inner().unwrap())
+}
+
+#[test]
+fn test_xerial_roundtrip() {
+    assert_eq!(&xerial_roundtrip(TEXT.as_bytes(), 32 * 1024)[..], TEXT.as_bytes());
+}
+
+#[test]
+fn test_xerial_roundtrip_many_blocks() {
+    assert_eq!(&xerial_roundtrip(TEXT.as_bytes(), 1000)[..], TEXT.as_bytes());
+}
+
+#[test]
+fn test_xerial_empty() {
+    let compressed = XerialWriter::new(Vec::new()).into_inner().unwrap();
+    assert_eq!(&compressed[..], &HEADER[..]);
+    assert!(xerial_decompress(compressed).is_empty());
+}
+
+#[test]
+fn test_xerial_block_layout() {
+    let mut writer = XerialWriter::new(Vec::new());
+    writer.write_all(&[1, 2, 3]).unwrap();
+    let compressed = writer.into_inner().unwrap();
+    assert_eq!(&compressed[..16], &HEADER[..]);
+    assert_eq!(&compressed[16..], &[0, 0, 0, 5 /* block length */,
+                                    3 /* uncompressed length */, 2 << 2 /* 3-byte literal */, 1, 2, 3]);
+}
+
+#[test]
+fn test_xerial_concatenated() {
+    let mut input = HEADER.to_vec();
+    input.extend_from_slice(&[0, 0, 0, 4, 2, 1 << 2, 1, 2]);
+    input.extend_from_slice(&HEADER);
+    input.extend_from_slice(&HEADER);
+    input.extend_from_slice(&[0, 0, 0, 3, 1, 0 << 2, 3]);
+    assert_eq!(&xerial_decompress(input)[..], &[1, 2, 3]);
+}
+
+#[test]
+fn test_xerial_bad_magic() {
+    let mut input = HEADER.to_vec();
+    input[1] = b's';
+    let mut out = Vec::new();
+    assert!(XerialReader::new(Cursor::new(input)).read_to_end(&mut out).is_err());
+}
+
+#[test]
+fn test_xerial_length_too_large() {
+    let mut input = HEADER.to_vec();
+    input.extend_from_slice(&[0, 0, 0, 6 /* block length */, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F /* 4 GiB */, 0]);
+    let mut out = Vec::new();
+    assert!(XerialReader::new(Cursor::new(input)).read_to_end(&mut out).is_err());
+}